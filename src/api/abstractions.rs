@@ -2,9 +2,12 @@
 // but also, enough abstractions so we can experiment and build more complex tools in the future
 #![allow(dead_code)]
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
+use super::metadata::ImgMetadata;
 use image::DynamicImage;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 /// Probabilities in the YOLO format
 /// `classes` is a Vec with the names for each classification
@@ -73,7 +76,7 @@ pub struct XYXYn {
     pub class_id: u16,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, ToSchema, Copy, Clone, Debug)]
 pub struct XYXY {
     pub x1: f32,
     pub y1: f32,
@@ -113,7 +116,8 @@ fn intersect_xyxys(x1: f32, y1: f32, x2: f32, y2: f32, x3: f32, y3: f32, x4: f32
     let x_right = x2.min(x4);
     let y_bottom = y2.min(y4);
 
-    (x_right - x_left) * (y_bottom - y_top)
+    // Boxes that don't overlap have no intersection
+    (x_right - x_left).max(0.0) * (y_bottom - y_top).max(0.0)
 }
 
 fn intersect_xywhs(x1: f32, y1: f32, w1: f32, h1: f32, x2: f32, y2: f32, w2: f32, h2: f32) -> f32 {
@@ -122,7 +126,7 @@ fn intersect_xywhs(x1: f32, y1: f32, w1: f32, h1: f32, x2: f32, y2: f32, w2: f32
     let x_right = (x1 + w1).min(x2 + w2);
     let y_bottom = (y1 + h1).min(y2 + h2);
 
-    (x_right - x_left) * (y_bottom - y_top)
+    (x_right - x_left).max(0.0) * (y_bottom - y_top).max(0.0)
 }
 
 fn iou<T: BoundingBoxTrait>(a: &T, b: &T) -> f32 {
@@ -556,7 +560,7 @@ impl BoundingBoxTrait for XYWH {
 }
 
 // AI model for Image Processing
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
 pub struct AI {
     pub name: String,
     pub version: f32, // complement tothe name
@@ -619,6 +623,8 @@ pub struct PredImg {
     pub file_path: PathBuf,
     pub list_bbox: Vec<XYXYc>,
     pub wasprocessed: bool,
+    // Read on first use, opening every file when a folder is picked is too slow on big SD cards
+    metadata: OnceLock<ImgMetadata>,
 }
 
 impl PredImg {
@@ -627,16 +633,17 @@ impl PredImg {
             file_path,
             list_bbox,
             wasprocessed,
+            metadata: OnceLock::new(),
         }
     }
 
     // Simple constructor: only file_path is provided
     pub fn new_simple(file_path: PathBuf) -> Self {
-        PredImg {
-            file_path,
-            list_bbox: Vec::new(),
-            wasprocessed: false,
-        }
+        Self::new(file_path, Vec::new(), false)
+    }
+
+    pub fn metadata(&self) -> &ImgMetadata {
+        self.metadata.get_or_init(|| ImgMetadata::read(&self.file_path))
     }
 
     pub fn draw(&self) -> Vec<u8> {
@@ -706,10 +713,23 @@ impl PredImgSugar for Vec<PredImg> {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
 pub struct XYXYc {
     pub xyxy: XYXY,
     pub label: String,
+    // The model that produced this prediction, only set when running an ensemble
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    // Stable id of the individual across video frames, only set when tracking
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track_id: Option<u32>,
+}
+
+impl XYXYc {
+    pub fn with_model(mut self, model: &str) -> Self {
+        self.model = Some(model.to_string());
+        self
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -744,7 +764,12 @@ pub trait BoundingBoxTraitC<T: BoundingBoxTrait> {
 
 impl BoundingBoxTraitC<XYXY> for XYXYc {
     fn new(xyxy: XYXY, label: String) -> Self {
-        Self { xyxy, label }
+        Self {
+            xyxy,
+            label,
+            model: None,
+            track_id: None,
+        }
     }
 
     fn to_xyxyc(&self, w: Option<f32>, h: Option<f32>, label: String) -> XYXYc {
@@ -764,7 +789,10 @@ impl BoundingBoxTraitC<XYXY> for XYXYc {
     }
 
     fn strlabel(&self) -> String {
-        detection_label(&self.label, &self.xyxy.prob)
+        match self.track_id {
+            Some(id) => format!("#{} {}", id, detection_label(&self.label, &self.xyxy.prob)),
+            None => detection_label(&self.label, &self.xyxy.prob),
+        }
     }
 }
 
@@ -0,0 +1,244 @@
+// Bearer token authentication for the REST API, off unless a keys file is given
+//
+// The keys file is JSON:
+// {"keys": [{"label": "station-1", "key": "...", "rate_per_minute": 60, "daily_quota": 5000}]}
+use super::v1::{ApiError, ErrorResponse};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+/// # Fields
+/// - `rate_per_minute` and `daily_quota` at 0 mean no limit
+/// - `admin` keys can read the usage of every key
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiKey {
+    pub label: String,
+    pub key: String,
+    #[serde(default)]
+    pub rate_per_minute: u32,
+    #[serde(default)]
+    pub daily_quota: u32,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeysFile {
+    keys: Vec<ApiKey>,
+}
+
+/// Counters of one key, the daily ones reset at local midnight
+/// # Fields
+/// - `tokens` is what is left of the per-minute bucket, refilled continuously
+#[derive(Clone, Debug)]
+struct Usage {
+    day: NaiveDate,
+    today: u32,
+    total: u64,
+    rejected: u64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct KeyUsage {
+    pub label: String,
+    pub today: u32,
+    pub daily_quota: u32,
+    pub total: u64,
+    pub rejected: u64,
+}
+
+pub enum Denied {
+    RateLimited(u64), // seconds until a request is allowed again
+    QuotaExceeded,
+}
+
+pub struct KeyStore {
+    keys: Vec<ApiKey>,
+    // SHA-256 of every key, in the same order, what tokens are compared with
+    digests: Vec<[u8; 32]>,
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl KeyStore {
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        Self {
+            digests: keys.iter().map(|key| Sha256::digest(key.key.as_bytes()).into()).collect(),
+            keys,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read the API keys in {}: {}", path, e))?;
+        let file: KeysFile = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse the API keys in {}: {}", path, e))?;
+        if file.keys.is_empty() {
+            return Err(format!("{} has no API keys", path));
+        }
+        Ok(Self::new(file.keys))
+    }
+
+    // The token is compared with every key in constant time, so how long it takes
+    // doesn't tell how much of a key was guessed
+    pub fn find(&self, token: &str) -> Option<&ApiKey> {
+        let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+        let mut found = None;
+        for (key, key_digest) in self.keys.iter().zip(&self.digests) {
+            if constant_time_eq(key_digest, &digest) && found.is_none() {
+                found = Some(key);
+            }
+        }
+        found
+    }
+
+    // Counts the request against the key, or says why it isn't allowed
+    pub fn register(&self, key: &ApiKey) -> Result<(), Denied> {
+        let now = Instant::now();
+        let today = Local::now().date_naive();
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(key.label.clone()).or_insert(Usage {
+            day: today,
+            today: 0,
+            total: 0,
+            rejected: 0,
+            tokens: key.rate_per_minute as f64,
+            refilled: now,
+        });
+
+        if usage.day != today {
+            usage.day = today;
+            usage.today = 0;
+        }
+        if key.daily_quota > 0 && usage.today >= key.daily_quota {
+            usage.rejected += 1;
+            return Err(Denied::QuotaExceeded);
+        }
+        if key.rate_per_minute > 0 {
+            let rate = key.rate_per_minute as f64 / 60.0;
+            usage.tokens = (usage.tokens + now.duration_since(usage.refilled).as_secs_f64() * rate)
+                .min(key.rate_per_minute as f64);
+            usage.refilled = now;
+            if usage.tokens < 1.0 {
+                usage.rejected += 1;
+                return Err(Denied::RateLimited(((1.0 - usage.tokens) / rate).ceil() as u64));
+            }
+            usage.tokens -= 1.0;
+        }
+
+        usage.today += 1;
+        usage.total += 1;
+        Ok(())
+    }
+
+    pub fn usage(&self) -> Vec<KeyUsage> {
+        let usage = self.usage.lock().unwrap();
+        let today = Local::now().date_naive();
+        self.keys
+            .iter()
+            .map(|key| {
+                let counters = usage.get(&key.label);
+                KeyUsage {
+                    label: key.label.clone(),
+                    today: counters
+                        .filter(|u| u.day == today)
+                        .map_or(0, |u| u.today),
+                    daily_quota: key.daily_quota,
+                    total: counters.map_or(0, |u| u.total),
+                    rejected: counters.map_or(0, |u| u.rejected),
+                }
+            })
+            .collect()
+    }
+}
+
+fn constant_time_eq(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim())
+}
+
+// Axum middleware, the key that was used is left in the request extensions
+pub async fn require_key(
+    State(store): State<Arc<KeyStore>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let key = match bearer_token(&request).and_then(|token| store.find(token)) {
+        Some(key) => key.clone(),
+        None => {
+            let mut response =
+                ApiError::new(StatusCode::UNAUTHORIZED, "Missing or unknown API key").into_response();
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            return response;
+        }
+    };
+
+    match store.register(&key) {
+        Ok(()) => (),
+        Err(Denied::RateLimited(retry_after)) => {
+            let mut response = ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("Rate limit of {} requests per minute reached", key.rate_per_minute),
+            )
+            .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after.max(1)));
+            return response;
+        }
+        Err(Denied::QuotaExceeded) => {
+            return ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("Daily quota of {} requests reached", key.daily_quota),
+            )
+            .into_response();
+        }
+    }
+
+    request.extensions_mut().insert(key);
+    next.run(request).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/admin/usage",
+    tag = "admin",
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Requests of every key", body = Vec<KeyUsage>),
+        (status = 403, description = "The key is not an admin key", body = ErrorResponse),
+    )
+)]
+pub async fn usage(
+    State(store): State<Arc<KeyStore>>,
+    request: Request,
+) -> Result<Json<Vec<KeyUsage>>, ApiError> {
+    match request.extensions().get::<ApiKey>() {
+        Some(key) if key.admin => Ok(Json(store.usage())),
+        _ => Err(ApiError::new(StatusCode::FORBIDDEN, "Only admin keys can read the usage")),
+    }
+}
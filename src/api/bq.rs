@@ -1,6 +1,6 @@
 use super::abstractions::AI;
 use std::fs::{self, File};
-use std::io::{self, Read};
+use std::io::{self, Read, Write};
 use std::path::Path;
 
 pub fn import_bq(file_path: &str) -> io::Result<(AI, Vec<u8>)> {
@@ -50,6 +50,20 @@ pub fn import_bq(file_path: &str) -> io::Result<(AI, Vec<u8>)> {
     Ok((ai_model, onnx_data))
 }
 
+// Writes the same layout that import_bq reads
+pub fn export_bq(file_path: &str, ai_model: &AI, onnx_data: &[u8]) -> io::Result<()> {
+    let json_str = serde_json::to_string(ai_model)?;
+
+    let mut file = File::create(file_path)?;
+    file.write_all(b"BQMODEL")?;
+    file.write_all(&[1])?;
+    file.write_all(&(json_str.len() as u32).to_le_bytes())?;
+    file.write_all(json_str.as_bytes())?;
+    file.write_all(&(onnx_data.len() as u32).to_le_bytes())?;
+    file.write_all(onnx_data)?;
+    Ok(())
+}
+
 pub fn get_ai_model(file_path: &str) -> io::Result<AI> {
     let mut file = File::open(file_path)?;
     let mut file_content = Vec::new();
@@ -119,5 +133,8 @@ fn analyze_folder(folder_path: &str) -> io::Result<Vec<AI>> {
 }
 
 pub fn get_bqs() -> Vec<AI> {
-    analyze_folder("models/").unwrap()
+    analyze_folder("models/").unwrap_or_else(|e| {
+        eprintln!("Couldn't read the models folder: {}", e);
+        Vec::new()
+    })
 }
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use utoipa::ToSchema;
+
+pub const CONFIG_PATH: &str = "config.json";
+
+/// Options used when building an ONNX Runtime session
+/// # Fields
+/// - `intra_threads` and `inter_threads` at 0 let ONNX Runtime decide
+/// - `optimization_level` goes from 0 (disabled) to 3 (all optimizations)
+/// - `cache_optimized` saves the optimized graph in `cache_dir`, so the next launch can skip optimization (CPU only)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub intra_threads: usize,
+    pub inter_threads: usize,
+    pub parallel_execution: bool,
+    pub memory_pattern: bool,
+    pub optimization_level: u8,
+    pub cache_optimized: bool,
+    pub cache_dir: String,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            intra_threads: 0,
+            inter_threads: 0,
+            parallel_execution: false,
+            memory_pattern: true,
+            optimization_level: 3,
+            cache_optimized: true,
+            cache_dir: "cache".to_string(),
+        }
+    }
+}
+
+/// Motion gate for feeds and video jobs, see `motion.rs`
+/// # Fields
+/// - `threshold` is the fraction of changed pixels (0 to 1) that counts as motion
+/// - `pixel_threshold` is how much a grayscale pixel (0 to 255) has to change to count
+/// - `cooldown_frames` keeps the detector running for a while after the motion stops
+/// - `learning_rate` is how fast the background adapts to light changes
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(default)]
+pub struct MotionConfig {
+    pub enabled: bool,
+    pub threshold: f32,
+    pub pixel_threshold: f32,
+    pub cooldown_frames: u32,
+    pub downscale_width: u32,
+    pub learning_rate: f32,
+}
+
+impl Default for MotionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 0.01,
+            pixel_threshold: 25.0,
+            cooldown_frames: 30,
+            downscale_width: 160,
+            learning_rate: 0.05,
+        }
+    }
+}
+
+/// Temporal smoothing for videos and streams, see `smoothing.rs`
+/// # Fields
+/// - `window` is the number of inference frames used to vote the label of a track
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(default)]
+pub struct SmoothingConfig {
+    pub enabled: bool,
+    pub window: usize,
+}
+
+impl Default for SmoothingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window: 5,
+        }
+    }
+}
+
+/// Where the REST API listens when deployed
+/// # Fields
+/// - `host` is the address to bind, 0.0.0.0 listens on every network interface
+/// - `tls_cert` and `tls_key` are PEM files, the API is served over HTTPS when both are set
+/// - `api_keys` is a JSON file with the keys allowed to use the API, see `auth.rs`. Anyone can use it when not set
+/// - `jobs_root` is the folder that jobs can read by path. Only uploads can be processed when not set
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub api_keys: Option<String>,
+    pub jobs_root: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 8791,
+            tls_cert: None,
+            tls_key: None,
+            api_keys: None,
+            jobs_root: None,
+        }
+    }
+}
+
+impl ApiConfig {
+    pub fn uses_tls(&self) -> bool {
+        self.tls_cert.is_some() && self.tls_key.is_some()
+    }
+}
+
+// Everything that can be set in config.json, missing fields fall back to their defaults
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub session: SessionConfig,
+    pub motion: MotionConfig,
+    pub smoothing: SmoothingConfig,
+    pub api: ApiConfig,
+}
+
+pub fn load_config(path: &str) -> Config {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            eprintln!("Failed to parse {}: {}, using defaults", path, e);
+            Config::default()
+        }),
+        Err(_) => Config::default(),
+    }
+}
+
+pub fn save_config(config: &Config, path: &str) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(config)?;
+    std::fs::write(path, content)
+}
@@ -0,0 +1,174 @@
+// Similarity search: one embedding per image (or per detection), stored in an index on disk
+use super::abstractions::{XYXYc, XYXY};
+use super::inference::{embed_imgbuf, get_embedder_name};
+use image::{imageops::crop_imm, ImageBuffer, Rgb};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const INDEX_PATH: &str = "export/embeddings.json";
+
+/// One vector in the index
+/// # Fields
+/// - `bbox` is the detection the vector was computed from, `None` when it's the whole image
+/// - `vector` is L2 normalized
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexEntry {
+    pub file_path: PathBuf,
+    pub bbox: Option<XYXY>,
+    pub vector: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Neighbour {
+    pub file_path: PathBuf,
+    pub bbox: Option<XYXY>,
+    pub similarity: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct EmbeddingIndex {
+    pub model: String,
+    pub entries: Vec<IndexEntry>,
+}
+
+pub fn crop(img: &ImageBuffer<Rgb<u8>, Vec<u8>>, xyxy: &XYXY) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let x1 = xyxy.x1.max(0.0) as u32;
+    let y1 = xyxy.y1.max(0.0) as u32;
+    let x2 = (xyxy.x2.max(0.0) as u32).min(img.width());
+    let y2 = (xyxy.y2.max(0.0) as u32).min(img.height());
+    crop_imm(img, x1, y1, x2.saturating_sub(x1).max(1), y2.saturating_sub(y1).max(1)).to_image()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+impl EmbeddingIndex {
+    // Uses the embedder that is currently loaded
+    pub fn new() -> Self {
+        Self {
+            model: get_embedder_name().unwrap_or_default(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn add_image(&mut self, file_path: &Path) -> io::Result<()> {
+        let img = image::open(file_path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .into_rgb8();
+        self.remove(file_path);
+        self.entries.push(IndexEntry {
+            file_path: file_path.to_path_buf(),
+            bbox: None,
+            vector: embed_imgbuf(&img),
+        });
+        Ok(())
+    }
+
+    pub fn add_detections(&mut self, file_path: &Path, list_bbox: &[XYXYc]) -> io::Result<()> {
+        let img = image::open(file_path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .into_rgb8();
+        self.remove(file_path);
+        for bbox in list_bbox {
+            self.entries.push(IndexEntry {
+                file_path: file_path.to_path_buf(),
+                bbox: Some(bbox.xyxy),
+                vector: embed_imgbuf(&crop(&img, &bbox.xyxy)),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, file_path: &Path) -> bool {
+        self.entries.iter().any(|entry| entry.file_path == file_path)
+    }
+
+    // Adding an image again replaces its entries, so it never shows up twice
+    pub fn remove(&mut self, file_path: &Path) {
+        self.entries.retain(|entry| entry.file_path != file_path);
+    }
+
+    // Brute force cosine similarity, fast enough for a season of camera trap images
+    pub fn nearest(&self, query: &[f32], k: usize) -> Vec<Neighbour> {
+        let mut neighbours: Vec<Neighbour> = self
+            .entries
+            .iter()
+            .map(|entry| Neighbour {
+                file_path: entry.file_path.clone(),
+                bbox: entry.bbox,
+                similarity: dot(query, &entry.vector),
+            })
+            .collect();
+        neighbours.sort_by(|a, b| {
+            b.similarity
+                .partial_cmp(&a.similarity)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        neighbours.truncate(k);
+        neighbours
+    }
+
+    // Neighbours of an image, the image itself is left out
+    pub fn similar_to(&self, file_path: &Path, k: usize) -> io::Result<Vec<Neighbour>> {
+        let query = match self.entries.iter().find(|entry| entry.file_path == file_path) {
+            Some(entry) => entry.vector.clone(),
+            None => {
+                let img = image::open(file_path)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .into_rgb8();
+                embed_imgbuf(&img)
+            }
+        };
+        let mut neighbours = self.nearest(&query, k + self.entries_of(file_path));
+        neighbours.retain(|n| n.file_path != file_path);
+        neighbours.truncate(k);
+        Ok(neighbours)
+    }
+
+    fn entries_of(&self, file_path: &Path) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.file_path == file_path)
+            .count()
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        if let Some(parent) = Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string(self)?;
+        std::fs::write(path, content)
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // Only a missing file starts a new index. Any other error is returned,
+    // saving an empty index over an unreadable one would lose its embeddings
+    pub fn load_or_new(path: &str) -> io::Result<Self> {
+        match Self::load(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            result => result,
+        }
+    }
+
+    // Vectors of different models can't be compared, the index must come from the loaded embedder
+    pub fn check_model(&self) -> io::Result<()> {
+        let loaded = get_embedder_name();
+        if loaded.as_deref() == Some(self.model.as_str()) {
+            return Ok(());
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "The index was built with '{}' but the loaded embedder is '{}'",
+                self.model,
+                loaded.unwrap_or_else(|| "none".to_string())
+            ),
+        ))
+    }
+}
@@ -0,0 +1,267 @@
+// Ensembles run several detectors over the same image and, optionally, fuse their predictions
+use super::abstractions::{BoundingBoxTrait, XYXYc, AI, XYXY};
+use super::bq::get_ai_model;
+use super::metrics;
+use super::models::{AIOutputs, Yolo};
+use image::{ImageBuffer, Rgb};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+pub const PIPELINE_EXTENSION: &str = "pipeline";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Fusion {
+    None,   // keep every prediction, tagged with its model
+    WBF,    // Weighted Boxes Fusion
+    Voting, // keep a box only if enough models agree on it
+}
+
+/// A named ensemble definition, saved as `models/{name}.pipeline`
+/// # Fields
+/// - `models` are the names of the `.bq` models in the `models/` folder
+/// - `iou_threshold` is the overlap needed for two boxes to be considered the same object
+/// - `min_votes` is the number of models that must agree on a box, only used with `Fusion::Voting`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Pipeline {
+    pub name: String,
+    pub models: Vec<String>,
+    pub fusion: Fusion,
+    #[serde(default = "default_iou_threshold")]
+    pub iou_threshold: f32,
+    #[serde(default = "default_min_votes")]
+    pub min_votes: usize,
+}
+
+fn default_iou_threshold() -> f32 {
+    0.55
+}
+
+fn default_min_votes() -> usize {
+    2
+}
+
+impl Pipeline {
+    pub fn new(name: String, models: Vec<String>, fusion: Fusion) -> Self {
+        Self {
+            name,
+            models,
+            fusion,
+            iou_threshold: default_iou_threshold(),
+            min_votes: default_min_votes(),
+        }
+    }
+
+    pub fn get_path(&self) -> String {
+        format!("models/{}.{}", self.name, PIPELINE_EXTENSION)
+    }
+
+    pub fn get_model_paths(&self) -> Vec<String> {
+        self.get_model_paths_in("models")
+    }
+
+    pub fn get_model_paths_in(&self, folder: &str) -> Vec<String> {
+        self.models
+            .iter()
+            .map(|name| format!("{}/{}.bq", folder, name.strip_suffix(".bq").unwrap_or(name)))
+            .collect()
+    }
+
+    // So the pipeline can be listed next to the regular models
+    pub fn to_ai(&self) -> AI {
+        self.to_ai_in("models")
+    }
+
+    // `models` are file names, so the metadata is read from each file instead of being
+    // matched by the name inside it, which can be different
+    pub fn to_ai_in(&self, folder: &str) -> AI {
+        let members: Vec<AI> = self
+            .get_model_paths_in(folder)
+            .iter()
+            .filter_map(|path| match get_ai_model(path) {
+                Ok(ai) => Some(ai),
+                Err(e) => {
+                    eprintln!("{} is part of {} but can't be read: {}", path, self.name, e);
+                    None
+                }
+            })
+            .collect();
+
+        let mut classes: Vec<String> = Vec::new();
+        for ai in &members {
+            for class in &ai.classes {
+                if !classes.contains(class) {
+                    classes.push(class.clone());
+                }
+            }
+        }
+
+        let (input_width, input_height) = members
+            .first()
+            .map(|ai| (ai.input_width, ai.input_height))
+            .unwrap_or((0, 0));
+
+        AI::new(
+            self.name.clone(),
+            0.0,
+            input_width,
+            input_height,
+            format!("Ensemble: {}", self.models.join(" + ")),
+            "green".to_string(),
+            "detect".to_string(),
+            vec![format!("{:?}", self.fusion)],
+            classes,
+        )
+    }
+}
+
+pub fn save_pipeline(pipeline: &Pipeline) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(pipeline)?;
+    fs::write(pipeline.get_path(), content)
+}
+
+pub fn import_pipeline(file_path: &str) -> io::Result<Pipeline> {
+    let content = fs::read_to_string(file_path)?;
+    serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn get_pipelines() -> Vec<Pipeline> {
+    let mut pipelines = Vec::new();
+    let Ok(entries) = fs::read_dir(Path::new("models/")) else {
+        return pipelines;
+    };
+
+    for entry in entries.flatten() {
+        let file_path = entry.path();
+        if file_path.extension().is_some_and(|ext| ext == PIPELINE_EXTENSION) {
+            match import_pipeline(file_path.to_str().unwrap()) {
+                Ok(pipeline) => pipelines.push(pipeline),
+                Err(e) => eprintln!("Error processing file {:?}: {}", file_path, e),
+            }
+        }
+    }
+    pipelines
+}
+
+pub struct Ensemble {
+    pub pipeline: Pipeline,
+    pub models: Vec<Yolo>,
+}
+
+impl Ensemble {
+    pub fn new(pipeline: Pipeline, models: Vec<Yolo>) -> Self {
+        Self { pipeline, models }
+    }
+
+    pub fn run(&self, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<XYXYc> {
+        let predictions: Vec<Vec<XYXYc>> = self
+            .models
+            .iter()
+            .map(|model| match model.run(img) {
+                AIOutputs::ObjectDetection(boxes) => boxes
+                    .into_iter()
+                    .map(|bbox| bbox.with_model(&model.name))
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+
+        let start = Instant::now();
+        let boxes = fuse(predictions, &self.pipeline);
+        metrics::observe_stage(metrics::POST_PROCESS, start);
+        boxes
+    }
+}
+
+pub fn fuse(predictions: Vec<Vec<XYXYc>>, pipeline: &Pipeline) -> Vec<XYXYc> {
+    let n_models = predictions.len();
+    let boxes: Vec<XYXYc> = predictions.into_iter().flatten().collect();
+    match pipeline.fusion {
+        Fusion::None => boxes,
+        Fusion::WBF => weighted_boxes_fusion(boxes, n_models, pipeline.iou_threshold),
+        Fusion::Voting => voting(boxes, pipeline.iou_threshold, pipeline.min_votes),
+    }
+}
+
+// Groups boxes with the same label that overlap the first (highest confidence) box of a cluster
+fn cluster(mut boxes: Vec<XYXYc>, iou_threshold: f32) -> Vec<Vec<XYXYc>> {
+    boxes.sort_by(|a, b| {
+        b.xyxy
+            .prob
+            .partial_cmp(&a.xyxy.prob)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let mut clusters: Vec<Vec<XYXYc>> = Vec::new();
+    for bbox in boxes {
+        let found = clusters.iter_mut().find(|cluster| {
+            cluster[0].label == bbox.label && cluster[0].xyxy.iou(&bbox.xyxy) > iou_threshold
+        });
+        match found {
+            Some(cluster) => cluster.push(bbox),
+            None => clusters.push(vec![bbox]),
+        }
+    }
+    clusters
+}
+
+fn model_names(cluster: &[XYXYc]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for bbox in cluster {
+        if let Some(model) = &bbox.model {
+            if !names.contains(model) {
+                names.push(model.clone());
+            }
+        }
+    }
+    names
+}
+
+// Weighted Boxes Fusion: coordinates are averaged using the confidence as weight,
+// and the confidence is lowered when only some of the models found the object
+fn weighted_boxes_fusion(boxes: Vec<XYXYc>, n_models: usize, iou_threshold: f32) -> Vec<XYXYc> {
+    cluster(boxes, iou_threshold)
+        .into_iter()
+        .map(|cluster| {
+            let total: f32 = cluster.iter().map(|b| b.xyxy.prob).sum();
+            let weighted = |f: fn(&XYXY) -> f32| -> f32 {
+                cluster.iter().map(|b| f(&b.xyxy) * b.xyxy.prob).sum::<f32>() / total
+            };
+            let models = model_names(&cluster);
+            let n_votes = models.len().max(1).min(n_models.max(1));
+            let prob = total / cluster.len() as f32 * n_votes as f32 / n_models.max(1) as f32;
+
+            let xyxy = XYXY::new(
+                weighted(|b| b.x1),
+                weighted(|b| b.y1),
+                weighted(|b| b.x2),
+                weighted(|b| b.y2),
+                prob,
+                cluster[0].xyxy.class_id,
+            );
+            let mut fused = cluster[0].clone();
+            fused.xyxy = xyxy;
+            fused.model = Some(models.join("+"));
+            fused
+        })
+        .collect()
+}
+
+// Keeps the best box of each cluster, only if it was found by at least `min_votes` models
+fn voting(boxes: Vec<XYXYc>, iou_threshold: f32, min_votes: usize) -> Vec<XYXYc> {
+    cluster(boxes, iou_threshold)
+        .into_iter()
+        .filter_map(|cluster| {
+            let models = model_names(&cluster);
+            if models.len() < min_votes {
+                return None;
+            }
+            let mut best = cluster[0].clone();
+            best.model = Some(models.join("+"));
+            Some(best)
+        })
+        .collect()
+}
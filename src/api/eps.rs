@@ -1,8 +1,20 @@
+use ort::execution_providers::{
+    CUDAExecutionProvider, ExecutionProvider as OrtExecutionProvider, ExecutionProviderDispatch,
+    TensorRTExecutionProvider,
+};
+#[cfg(feature = "openvino")]
+use ort::execution_providers::OpenVINOExecutionProvider;
+#[cfg(feature = "rocm")]
+use ort::execution_providers::ROCmExecutionProvider;
+#[cfg(feature = "xnnpack")]
+use ort::execution_providers::XNNPACKExecutionProvider;
+use ort::session::Session;
 use regex::Regex;
-use std::os::windows::process::CommandExt;
 use std::process::Command;
 use std::str;
 
+/// # Fields
+/// - `fallback` is the name of the EP to try when this one can't be used, `None` for the end of the chain
 #[derive(Clone)]
 pub struct EP {
     pub name: &'static str,
@@ -10,24 +22,249 @@ pub struct EP {
     pub version: f32,
     pub local: bool,
     pub dependencies: &'static str,
+    pub fallback: Option<&'static str>,
+}
+
+// A provider ONNX Runtime can run a session on
+pub trait ExecutionProvider {
+    fn name(&self) -> &'static str;
+    // Ok when the provider can be used on this machine, otherwise the reason why not
+    fn check(&self) -> Result<(), String>;
+    // None means the default CPU provider, which needs no registration
+    fn dispatch(&self) -> Option<ExecutionProviderDispatch>;
+}
+
+// Asks ONNX Runtime if the provider is part of its build, then registers it on an empty
+// session, which fails when the drivers or the libraries it needs are missing
+fn check_ort_provider<T>(provider: T) -> Result<(), String>
+where
+    T: OrtExecutionProvider + Into<ExecutionProviderDispatch>,
+{
+    match provider.is_available() {
+        Ok(true) => (),
+        Ok(false) => {
+            return Err(format!(
+                "{} is not part of this ONNX Runtime build",
+                provider.as_str()
+            ))
+        }
+        Err(e) => return Err(e.to_string()),
+    }
+    let dispatch: ExecutionProviderDispatch = provider.into();
+    Session::builder()
+        .and_then(|builder| builder.with_execution_providers([dispatch.error_on_failure()]))
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+pub struct CpuProvider;
+pub struct CudaProvider;
+pub struct TensorRTProvider;
+pub struct OpenVINOProvider;
+pub struct ROCmProvider;
+pub struct XNNPACKProvider;
+
+impl ExecutionProvider for CpuProvider {
+    fn name(&self) -> &'static str {
+        "CPU"
+    }
+
+    fn check(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn dispatch(&self) -> Option<ExecutionProviderDispatch> {
+        None
+    }
+}
+
+impl ExecutionProvider for CudaProvider {
+    fn name(&self) -> &'static str {
+        "CUDA"
+    }
+
+    fn check(&self) -> Result<(), String> {
+        check_ort_provider(CUDAExecutionProvider::default())
+    }
+
+    fn dispatch(&self) -> Option<ExecutionProviderDispatch> {
+        Some(CUDAExecutionProvider::default().build())
+    }
+}
+
+impl ExecutionProvider for TensorRTProvider {
+    fn name(&self) -> &'static str {
+        "TensorRT"
+    }
+
+    fn check(&self) -> Result<(), String> {
+        check_ort_provider(TensorRTExecutionProvider::default())
+    }
+
+    fn dispatch(&self) -> Option<ExecutionProviderDispatch> {
+        Some(TensorRTExecutionProvider::default().build())
+    }
+}
+
+impl ExecutionProvider for OpenVINOProvider {
+    fn name(&self) -> &'static str {
+        "OpenVINO"
+    }
+
+    fn check(&self) -> Result<(), String> {
+        #[cfg(feature = "openvino")]
+        return check_ort_provider(OpenVINOExecutionProvider::default());
+        #[cfg(not(feature = "openvino"))]
+        return Err("built without the openvino feature".to_string());
+    }
+
+    fn dispatch(&self) -> Option<ExecutionProviderDispatch> {
+        #[cfg(feature = "openvino")]
+        return Some(OpenVINOExecutionProvider::default().build());
+        #[cfg(not(feature = "openvino"))]
+        return None;
+    }
+}
+
+impl ExecutionProvider for ROCmProvider {
+    fn name(&self) -> &'static str {
+        "ROCm"
+    }
+
+    fn check(&self) -> Result<(), String> {
+        #[cfg(feature = "rocm")]
+        return check_ort_provider(ROCmExecutionProvider::default());
+        #[cfg(not(feature = "rocm"))]
+        return Err("built without the rocm feature".to_string());
+    }
+
+    fn dispatch(&self) -> Option<ExecutionProviderDispatch> {
+        #[cfg(feature = "rocm")]
+        return Some(ROCmExecutionProvider::default().build());
+        #[cfg(not(feature = "rocm"))]
+        return None;
+    }
+}
+
+impl ExecutionProvider for XNNPACKProvider {
+    fn name(&self) -> &'static str {
+        "XNNPACK"
+    }
+
+    fn check(&self) -> Result<(), String> {
+        #[cfg(feature = "xnnpack")]
+        return check_ort_provider(XNNPACKExecutionProvider::default());
+        #[cfg(not(feature = "xnnpack"))]
+        return Err("built without the xnnpack feature".to_string());
+    }
+
+    fn dispatch(&self) -> Option<ExecutionProviderDispatch> {
+        #[cfg(feature = "xnnpack")]
+        return Some(XNNPACKExecutionProvider::default().build());
+        #[cfg(not(feature = "xnnpack"))]
+        return None;
+    }
+}
+
+impl EP {
+    // None for the remote EP, inference happens on another machine
+    pub fn provider(&self) -> Option<Box<dyn ExecutionProvider>> {
+        match self.name {
+            "CPU" => Some(Box::new(CpuProvider)),
+            "CUDA" => Some(Box::new(CudaProvider)),
+            "TensorRT" => Some(Box::new(TensorRTProvider)),
+            "OpenVINO" => Some(Box::new(OpenVINOProvider)),
+            "ROCm" => Some(Box::new(ROCmProvider)),
+            "XNNPACK" => Some(Box::new(XNNPACKProvider)),
+            _ => None,
+        }
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        match self.provider() {
+            Some(provider) => provider.check(),
+            None if !self.local => Ok(()),
+            None => Err(format!("{} is not a known execution provider", self.name)),
+        }
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.check().is_ok()
+    }
+}
+
+// A provider that was passed over while loading a model
+#[derive(Clone, Debug)]
+pub struct Skipped {
+    pub name: &'static str,
+    pub reason: String,
+}
+
+// The EP itself followed by its fallbacks, e.g. TensorRT -> CUDA -> CPU
+pub fn fallback_chain(ep: &EP) -> Vec<EP> {
+    let mut chain = vec![ep.clone()];
+    let mut next = ep.fallback;
+    while let Some(name) = next {
+        match LIST_EPS.iter().find(|e| e.name == name) {
+            Some(fallback) if !chain.iter().any(|e| e.name == name) => {
+                chain.push(fallback.clone());
+                next = fallback.fallback;
+            }
+            _ => break,
+        }
+    }
+    chain
+}
+
+// The EPs that can be used on this machine, in the order of LIST_EPS
+pub fn available_eps() -> Vec<EP> {
+    LIST_EPS.iter().filter(|ep| ep.is_available()).cloned().collect()
+}
+
+// The EPs that work without probing anything, the CPU and the remote EP. Shown while
+// available_eps() runs, which can take seconds when it loads the GPU libraries
+pub fn unprobed_eps() -> Vec<EP> {
+    LIST_EPS
+        .iter()
+        .filter(|ep| ep.name == "CPU" || !ep.local)
+        .cloned()
+        .collect()
+}
+
+// The first local EP that works, CPU in the worst case
+pub fn best_ep() -> EP {
+    const PREFERENCE: [&str; 6] = ["TensorRT", "CUDA", "ROCm", "OpenVINO", "XNNPACK", "CPU"];
+    PREFERENCE
+        .iter()
+        .map(|name| get_ep_by_name(LIST_EPS, name))
+        .find(|ep| ep.is_available())
+        .unwrap_or_else(|| get_ep_by_name(LIST_EPS, "CPU"))
 }
 
 pub const CREATE_NO_WINDOW: u32 = 0x08000000;
 
+// On Windows the command would otherwise flash a console window over the GUI
+fn hidden_command(program: &str) -> Command {
+    #[allow(unused_mut)]
+    let mut command = Command::new(program);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    command
+}
+
 pub fn get_ep_version(provider: &EP) -> f64 {
     match provider.name {
         "CUDA" => {
-            let output = Command::new("nvcc")
-                .args(["--version"])
-                .creation_flags(CREATE_NO_WINDOW)
-                .output()
-                .unwrap();
-
-            let output_text = match str::from_utf8(&output.stdout) {
-                Ok(v) => v,
-                Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+            let output = match hidden_command("nvcc").args(["--version"]).output() {
+                Ok(output) => output,
+                Err(_) => return 0.0, // nvcc is not installed
             };
 
+            let output_text = str::from_utf8(&output.stdout).unwrap_or("");
+
             let version_regex = Regex::new(r"release (\d+\.\d+),").unwrap();
 
             if let Some(captures) = version_regex.captures(output_text) {
@@ -38,22 +275,8 @@ pub fn get_ep_version(provider: &EP) -> f64 {
             }
             0.0 // Return 0.0 if no match is found
         }
-        "CPU" => {
-            todo!();
-        }
-        "ROCm" => {
-            todo!();
-        }
-        "VitisAI" => {
-            todo!();
-        }
-        "TensorRT" => {
-            todo!();
-        }
-        "BoquilaHUBRemoto" => {
-            todo!();
-        }
-        _ => 0.0, // Default case
+        // No version to probe for the others, the version listed in LIST_EPS is used
+        _ => provider.version as f64,
     }
 }
 
@@ -68,6 +291,7 @@ pub static LIST_EPS: &[EP] = &[
         version: 0.0,
         local: true,
         dependencies: "none",
+        fallback: None,
     },
     EP {
         name: "CUDA",
@@ -75,6 +299,39 @@ pub static LIST_EPS: &[EP] = &[
         version: 12.4,
         local: true,
         dependencies: "cuDNN",
+        fallback: Some("CPU"),
+    },
+    EP {
+        name: "TensorRT",
+        img_path: "tiny_nvidia.png",
+        version: 10.0,
+        local: true,
+        dependencies: "CUDA, cuDNN, TensorRT",
+        fallback: Some("CUDA"),
+    },
+    EP {
+        name: "ROCm",
+        img_path: "tiny_amd.png",
+        version: 6.0,
+        local: true,
+        dependencies: "ROCm",
+        fallback: Some("CPU"),
+    },
+    EP {
+        name: "OpenVINO",
+        img_path: "tiny_intel.png",
+        version: 2024.0,
+        local: true,
+        dependencies: "OpenVINO",
+        fallback: Some("CPU"),
+    },
+    EP {
+        name: "XNNPACK",
+        img_path: "tiny_cpu.png",
+        version: 0.0,
+        local: true,
+        dependencies: "none",
+        fallback: Some("CPU"),
     },
     EP {
         name: "BoquilaHUB Remoto",
@@ -82,5 +339,6 @@ pub static LIST_EPS: &[EP] = &[
         version: 0.0,
         local: false,
         dependencies: "none",
+        fallback: Some("CPU"),
     },
-];
\ No newline at end of file
+];
@@ -3,6 +3,11 @@ use super::abstractions::BoundingBoxTrait;
 use super::abstractions::PredImg;
 use super::abstractions::XYXYc;
 use super::abstractions::XYXY;
+use super::metadata::METADATA_HEADERS;
+use super::sequence::{main_label, Event};
+use super::tracking::{count_individuals, TrackSummary};
+use super::triage::{triage, TriageClass};
+use super::zones::BinCount;
 use csv::Writer;
 use csv::WriterBuilder;
 use std::collections::HashSet;
@@ -12,11 +17,14 @@ use std::path::Path;
 
 pub fn write_csv(pred_imgs: Vec<PredImg>, output_path: &str) -> io::Result<()> {
     let mut wtr = Writer::from_path(output_path)?;
-    wtr.write_record(&["File Path", "X1", "Y1", "X2", "Y2", "Label", "Confidence"])?;
+    let mut headers = vec!["File Path", "X1", "Y1", "X2", "Y2", "Label", "Confidence", "Model"];
+    headers.extend(METADATA_HEADERS);
+    wtr.write_record(&headers)?;
 
     for pred_img in pred_imgs {
+        let metadata = pred_img.metadata().to_record();
         for bbox in pred_img.list_bbox {
-            wtr.write_record(&[
+            let mut record = vec![
                 pred_img.file_path.to_string_lossy().into_owned(),
                 bbox.xyxy.x1.to_string(),
                 bbox.xyxy.y1.to_string(),
@@ -24,7 +32,10 @@ pub fn write_csv(pred_imgs: Vec<PredImg>, output_path: &str) -> io::Result<()> {
                 bbox.xyxy.y2.to_string(),
                 bbox.xyxy.class_id.to_string(),
                 bbox.xyxy.prob.to_string(),
-            ])?;
+                bbox.model.unwrap_or_default(),
+            ];
+            record.extend(metadata.iter().cloned());
+            wtr.write_record(&record)?;
         }
     }
 
@@ -36,7 +47,9 @@ pub fn write_csv2(pred_imgs: Vec<PredImg>, output_path: &str) -> io::Result<()>
     let file = File::create(output_path)?;
     let mut wtr = WriterBuilder::new().has_headers(true).from_writer(file);
 
-    wtr.write_record(&["File Path", "n", "observaciones"])?;
+    let mut headers = vec!["File Path", "n", "observaciones"];
+    headers.extend(METADATA_HEADERS);
+    wtr.write_record(&headers)?;
 
     // Iterate through each predicted image.
     for pred_img in pred_imgs {
@@ -61,14 +74,104 @@ pub fn write_csv2(pred_imgs: Vec<PredImg>, output_path: &str) -> io::Result<()>
 
         // Write a row for the predicted image, including the count of bounding boxes
         // and the unique labels.
+        let mut record = vec![
+            pred_img.file_path.to_string_lossy().into_owned(),
+            bbox_rows.len().to_string(),
+            labels.into_iter().collect::<Vec<String>>().join(", "),
+        ];
+        record.extend(pred_img.metadata().to_record());
+        wtr.write_record(&record)?;
+    }
+
+    // Flush and write the CSV.
+    wtr.flush()?;
+    Ok(())
+}
+
+// Event rows are followed by the rows of the images that belong to them
+pub fn write_csv_events(pred_imgs: &[PredImg], events: &[Event], output_path: &str) -> io::Result<()> {
+    let mut wtr = Writer::from_path(output_path)?;
+    wtr.write_record(&["Level", "Event", "File Path", "Capture Time", "Label", "Confidence", "n"])?;
+
+    for event in events {
         wtr.write_record(&[
-            &pred_img.file_path.to_string_lossy().into_owned(),
-            &bbox_rows.len().to_string(),
-            &labels.into_iter().collect::<Vec<String>>().join(", "),
+            "event".to_string(),
+            event.id.to_string(),
+            event.folder.to_string_lossy().into_owned(),
+            format!("{} - {}", event.start, event.end),
+            event.label.clone(),
+            event.confidence.to_string(),
+            event.count.to_string(),
         ])?;
+
+        for &i in &event.images {
+            let pred_img = &pred_imgs[i];
+            let (label, confidence) = main_label(&pred_img.list_bbox);
+            wtr.write_record(&[
+                "image".to_string(),
+                event.id.to_string(),
+                pred_img.file_path.to_string_lossy().into_owned(),
+                pred_img
+                    .metadata()
+                    .capture_time
+                    .map(|t| t.to_string())
+                    .unwrap_or_default(),
+                label,
+                confidence.to_string(),
+                pred_img.list_bbox.len().to_string(),
+            ])?;
+        }
     }
 
-    // Flush and write the CSV.
+    wtr.flush()?;
+    Ok(())
+}
+
+// One row per individual
+pub fn write_csv_tracks(tracks: &[TrackSummary], output_path: &str) -> io::Result<()> {
+    let mut wtr = Writer::from_path(output_path)?;
+    wtr.write_record(&["Track", "Label", "First Seen", "Last Seen", "Frames", "Max Confidence"])?;
+
+    for track in tracks {
+        wtr.write_record(&[
+            track.id.to_string(),
+            track.label.clone(),
+            format!("{:.3}", track.first_seen),
+            format!("{:.3}", track.last_seen),
+            track.n_frames.to_string(),
+            track.max_prob.to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
+
+// Number of individuals per label
+pub fn write_csv_track_counts(tracks: &[TrackSummary], output_path: &str) -> io::Result<()> {
+    let mut wtr = Writer::from_path(output_path)?;
+    wtr.write_record(&["Label", "Individuals"])?;
+    for (label, n) in count_individuals(tracks) {
+        wtr.write_record(&[label, n.to_string()])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+// Zone and line crossings per time bin, times are in seconds from the start
+pub fn write_csv_zone_counts(bins: &[BinCount], output_path: &str) -> io::Result<()> {
+    let mut wtr = Writer::from_path(output_path)?;
+    wtr.write_record(&["Bin Start", "Bin End", "Zone", "Label", "Direction", "Count"])?;
+    for bin in bins {
+        wtr.write_record(&[
+            bin.start.to_string(),
+            bin.end.to_string(),
+            bin.zone.clone(),
+            bin.label.clone(),
+            bin.direction.as_str().to_string(),
+            bin.count.to_string(),
+        ])?;
+    }
     wtr.flush()?;
     Ok(())
 }
@@ -150,6 +253,8 @@ pub async fn read_predictions_from_file(input_path: &str) -> io::Result<Vec<XYXY
                 bboxes.push(XYXYc {
                     xyxy: XYXY::new(x1, y1, x2, y2, confidence, class_id),
                     label: parts[0].to_string(),
+                    model: None,
+                    track_id: None,
                 });
             }
             _ => {
@@ -216,7 +321,7 @@ pub fn are_boxes_empty(images: &Vec<PredImg>) -> bool {
 // Get the most frequent label from a list of bounding boxes
 fn get_main_label(listbbox: &Vec<XYXYc>) -> String {
     if listbbox.is_empty() {
-        return String::from("no predictions");
+        return String::from(TriageClass::Empty.as_str());
     } else {
         let mut label_counts: std::collections::HashMap<&String, usize> =
             std::collections::HashMap::new();
@@ -235,31 +340,88 @@ fn get_main_label(listbbox: &Vec<XYXYc>) -> String {
     }
 }
 
-pub async fn copy_to_folder(pred_imgs: &Vec<PredImg>, output_path: &str) {
+// How files are placed in the output folders
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FileMode {
+    Copy,
+    Move,
+    Link,
+}
+
+impl From<&str> for FileMode {
+    fn from(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "move" => FileMode::Move,
+            "link" => FileMode::Link,
+            _ => FileMode::Copy,
+        }
+    }
+}
+
+async fn place_file(image_file_path: &Path, folder_path: &str, mode: FileMode) -> io::Result<()> {
+    // Create directory if it doesn't exist
+    tokio::fs::create_dir_all(folder_path).await?;
+
+    // Extract the image name from path
+    let image_name = image_file_path.file_name().unwrap_or_default();
+    let new_image_path = Path::new(folder_path).join(image_name);
+
+    match mode {
+        FileMode::Copy => {
+            tokio::fs::copy(image_file_path, &new_image_path).await?;
+        }
+        FileMode::Move => {
+            // rename fails across drives, so we fall back to copy and delete
+            if tokio::fs::rename(image_file_path, &new_image_path).await.is_err() {
+                tokio::fs::copy(image_file_path, &new_image_path).await?;
+                tokio::fs::remove_file(image_file_path).await?;
+            }
+        }
+        FileMode::Link => {
+            // Hard links only work within the same drive, so we fall back to a copy
+            if tokio::fs::hard_link(image_file_path, &new_image_path).await.is_err() {
+                tokio::fs::copy(image_file_path, &new_image_path).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// One folder per main label, images without predictions go to "empty"
+pub async fn copy_to_folder(pred_imgs: &Vec<PredImg>, output_path: &str, mode: FileMode) {
     for pred_img in pred_imgs {
         let image_file_path = &pred_img.file_path;
-        if std::path::Path::new(image_file_path).exists() {
+        if image_file_path.exists() {
             let main_label = get_main_label(&pred_img.list_bbox);
-
             let folder_path = format!("{}/{}", output_path, main_label);
 
-            // Create directory if it doesn't exist
-            if !std::path::Path::new(&folder_path).exists() {
-                std::fs::create_dir_all(&folder_path).unwrap();
+            if let Err(e) = place_file(image_file_path, &folder_path, mode).await {
+                eprintln!("Failed to place {:?}: {}", image_file_path, e);
             }
+        }
+    }
+}
 
-            // Extract the image name from path
-            let image_name = std::path::Path::new(image_file_path)
-                .file_name()
-                .unwrap_or_default()
-                .to_str()
-                .unwrap_or_default();
-
-            let new_image_path = format!("{}/{}", folder_path, image_name);
-
-            // Read the original file and write to the new location
-            let image_data = tokio::fs::read(image_file_path).await.unwrap();
-            tokio::fs::write(&new_image_path, image_data).await.unwrap();
+// Splits the images into `empty/` and `non_empty/{animal,person,vehicle}/`
+pub async fn sort_by_triage(
+    pred_imgs: &Vec<PredImg>,
+    output_path: &str,
+    threshold: f32,
+    mode: FileMode,
+) {
+    for pred_img in pred_imgs.iter().filter(|img| img.wasprocessed) {
+        let image_file_path = &pred_img.file_path;
+        if image_file_path.exists() {
+            let class = triage(&pred_img.list_bbox, threshold);
+            let folder_path = if class.is_empty() {
+                format!("{}/empty", output_path)
+            } else {
+                format!("{}/non_empty/{}", output_path, class.as_str())
+            };
+
+            if let Err(e) = place_file(image_file_path, &folder_path, mode).await {
+                eprintln!("Failed to place {:?}: {}", image_file_path, e);
+            }
         }
     }
 }
@@ -0,0 +1,94 @@
+// Liveness and readiness of a deployed server, for load balancers and remote clients
+//
+// /healthz answers as long as the process does, /readyz only once a model is loaded
+// and has run its first prediction, so the first real request isn't the slow one
+use super::inference::{get_loaded_model, is_warm};
+use super::v1::ModelRef;
+use axum::{http::StatusCode, Json};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+static STARTED: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// # Fields
+/// - `status` is "ok" for /healthz, "ready", "no_model" or "warming_up" for /readyz
+/// - `version` of BoquilaHUB, not of the model
+/// - `ep` the model was loaded on, e.g. "CUDA"
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct Health {
+    pub status: String,
+    pub version: String,
+    pub model: Option<ModelRef>,
+    pub ep: Option<String>,
+    pub uptime_secs: u64,
+}
+
+impl Health {
+    fn new(status: &str) -> Self {
+        let loaded = get_loaded_model();
+        Self {
+            status: status.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            ep: loaded.as_ref().map(|model| model.ep.to_string()),
+            model: loaded.map(|model| ModelRef {
+                name: model.name,
+                version: model.version,
+            }),
+            uptime_secs: STARTED.elapsed().as_secs(),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.status == "ready"
+    }
+}
+
+// Uptime is counted from the first call, the API calls it when it starts serving
+pub fn start_uptime() {
+    Lazy::force(&STARTED);
+}
+
+pub fn liveness() -> Health {
+    Health::new("ok")
+}
+
+pub fn readiness() -> Health {
+    let mut health = Health::new("ready");
+    if health.model.is_none() {
+        health.status = "no_model".to_string();
+    } else if !is_warm() {
+        health.status = "warming_up".to_string();
+    }
+    health
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "monitoring",
+    responses((status = 200, description = "The process is alive", body = Health))
+)]
+pub async fn healthz() -> Json<Health> {
+    Json(liveness())
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "monitoring",
+    responses(
+        (status = 200, description = "A model is loaded and warm", body = Health),
+        (status = 503, description = "No model loaded, or it's still warming up", body = Health),
+    )
+)]
+pub async fn readyz() -> (StatusCode, Json<Health>) {
+    let health = readiness();
+    let status = if health.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(health))
+}
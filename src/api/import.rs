@@ -1,3 +1,7 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
 // First formats
 // then, some logic and checks
 pub const IMAGE_FORMATS: [&'static str; 24] = [
@@ -77,4 +81,17 @@ pub fn is_supported_videofile(file_path: &str) -> bool {
         return VIDEO_FORMATS.contains(&extension.to_lowercase().as_str());
     }
     false
-}
\ No newline at end of file
+}
+
+// All the supported images in a folder, subfolders are ignored
+pub fn get_images_in_folder(folder_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut image_files = Vec::new();
+    for entry in fs::read_dir(folder_path)? {
+        let path = entry?.path();
+        if path.is_file() && is_supported_img(&path.to_string_lossy()) {
+            image_files.push(path);
+        }
+    }
+    image_files.sort();
+    Ok(image_files)
+}
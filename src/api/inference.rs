@@ -1,13 +1,21 @@
 #![allow(dead_code)]
 use super::abstractions::{XYXYc, AI};
 use super::bq::import_bq;
-use super::eps::EP;
-use super::models::{AIOutputs, Task, Yolo};
+use super::config::SessionConfig;
+use super::ensemble::{Ensemble, Pipeline};
+use super::eps::{fallback_chain, Skipped, EP};
+use super::metrics;
+use super::models::{AIOutputs, Embedder, Task, Yolo, CONFIDENCE_THRESHOLD};
+use super::remote::RemoteClient;
+use super::utils::image_buffer_to_jpg_buffer;
 use image::{open, ImageBuffer, Rgb};
 use once_cell::sync::Lazy;
-use ort::session::builder::GraphOptimizationLevel;
-use ort::{execution_providers::CUDAExecutionProvider, session::Session};
-use std::sync::Mutex;
+use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
+use ort::session::Session;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
 
 pub fn init_app() {
     std::fs::create_dir_all("output_feed").unwrap();
@@ -15,71 +23,359 @@ pub fn init_app() {
 }
 
 // Lazily initialized global variables for the MODEL
-static CURRENT_AI: Lazy<Mutex<Yolo>> = Lazy::new(|| Mutex::new(Yolo::default())); //
-
-fn import_model(model_data: &Vec<u8>, ep: EP) -> Session {
-    if ep.name == "CUDA" {
-        let model = Session::builder()
-            .unwrap()
-            .with_execution_providers([CUDAExecutionProvider::default().build().error_on_failure()])
-            .unwrap()
-            .with_optimization_level(GraphOptimizationLevel::Level3)
-            .unwrap()
-            .commit_from_memory(&model_data)
-            .unwrap();
-
-        return model;
-    } else {
-        let model = Session::builder()
-            .unwrap()
-            .with_optimization_level(GraphOptimizationLevel::Level3)
-            .unwrap()
-            .commit_from_memory(&model_data)
-            // .with_execution_providers([cuda.build()]).unwrap()
-            .unwrap();
-
-        return model;
+static CURRENT_AI: Lazy<Mutex<Option<Yolo>>> = Lazy::new(|| Mutex::new(None));
+static CURRENT_ENSEMBLE: Lazy<Mutex<Option<Ensemble>>> = Lazy::new(|| Mutex::new(None));
+static CURRENT_EMBEDDER: Lazy<Mutex<Option<Embedder>>> = Lazy::new(|| Mutex::new(None));
+static CURRENT_REMOTE: Lazy<Mutex<Option<RemoteClient>>> = Lazy::new(|| Mutex::new(None));
+// The EP the last model was loaded on, and the ones that were skipped to get there
+static ACTIVE_EP: Lazy<Mutex<Option<(&'static str, Vec<Skipped>)>>> = Lazy::new(|| Mutex::new(None));
+
+// What answers predictions, kept apart from the model locks, which are held while predicting,
+// so the health probes and /metrics don't wait for inference
+#[derive(Clone, Debug)]
+pub struct LoadedModel {
+    pub name: String,
+    pub version: f32,
+    pub ep: &'static str,
+}
+static LOADED: Lazy<RwLock<Option<LoadedModel>>> = Lazy::new(|| RwLock::new(None));
+
+// Set once the loaded model has made a prediction, the first one is much slower
+static WARM: AtomicBool = AtomicBool::new(false);
+
+pub fn get_active_ep() -> Option<(&'static str, Vec<Skipped>)> {
+    ACTIVE_EP.lock().unwrap().clone()
+}
+
+fn optimization_level(level: u8) -> GraphOptimizationLevel {
+    match level {
+        0 => GraphOptimizationLevel::Disable,
+        1 => GraphOptimizationLevel::Level1,
+        2 => GraphOptimizationLevel::Level2,
+        _ => GraphOptimizationLevel::Level3,
     }
 }
 
-pub fn set_model(value: String, ep: EP) {
-    let (model_metadata, data): (AI, Vec<u8>) = import_bq(&value).unwrap();
+// Key of the optimized model cache. SHA-256 so it stays the same across Rust releases,
+// the options that change the optimized graph are part of it
+pub fn model_cache_key(model_data: &[u8], config: &SessionConfig) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model_data);
+    hasher.update([
+        config.optimization_level,
+        config.parallel_execution as u8,
+        config.memory_pattern as u8,
+    ]);
+    hasher.finalize()[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn session_builder(config: &SessionConfig) -> SessionBuilder {
+    let mut builder = Session::builder()
+        .unwrap()
+        .with_parallel_execution(config.parallel_execution)
+        .unwrap()
+        .with_memory_pattern(config.memory_pattern)
+        .unwrap();
+
+    if config.intra_threads > 0 {
+        builder = builder.with_intra_threads(config.intra_threads).unwrap();
+    }
+    if config.inter_threads > 0 {
+        builder = builder.with_inter_threads(config.inter_threads).unwrap();
+    }
+    builder
+}
+
+// Tries the EP and then its fallbacks, until one of them can load the model
+fn import_model(model_data: &Vec<u8>, ep: EP, config: &SessionConfig) -> Session {
+    let mut skipped: Vec<Skipped> = Vec::new();
+
+    for candidate in fallback_chain(&ep) {
+        let Some(provider) = candidate.provider() else {
+            skipped.push(Skipped {
+                name: candidate.name,
+                reason: "runs inference on another machine".to_string(),
+            });
+            continue;
+        };
+        if let Err(reason) = provider.check() {
+            skipped.push(Skipped {
+                name: candidate.name,
+                reason,
+            });
+            continue;
+        }
+
+        let session = match provider.dispatch() {
+            None => Ok(import_model_cpu(model_data, config)),
+            Some(dispatch) => session_builder(config)
+                .with_execution_providers([dispatch.error_on_failure()])
+                .and_then(|builder| {
+                    builder.with_optimization_level(optimization_level(config.optimization_level))
+                })
+                .and_then(|builder| builder.commit_from_memory(&model_data)),
+        };
+
+        match session {
+            Ok(session) => {
+                for skip in &skipped {
+                    eprintln!("Skipped {}: {}", skip.name, skip.reason);
+                }
+                *ACTIVE_EP.lock().unwrap() = Some((candidate.name, skipped));
+                return session;
+            }
+            Err(e) => skipped.push(Skipped {
+                name: candidate.name,
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    // Every chain ends with the CPU, which is always there
+    *ACTIVE_EP.lock().unwrap() = Some(("CPU", skipped));
+    import_model_cpu(model_data, config)
+}
+
+fn import_model_cpu(model_data: &Vec<u8>, config: &SessionConfig) -> Session {
+    let builder = session_builder(config);
+
+    // An optimized graph is tied to the hardware it was optimized for, so we only cache it for the CPU
+    if config.cache_optimized && config.optimization_level > 0 {
+        let cache_path = Path::new(&config.cache_dir).join(format!(
+            "{}_O{}.onnx",
+            model_cache_key(model_data, config),
+            config.optimization_level
+        ));
+
+        if cache_path.exists() {
+            // The cached graph is already optimized, no need to do it again
+            match builder
+                .clone()
+                .with_optimization_level(GraphOptimizationLevel::Disable)
+                .unwrap()
+                .commit_from_file(&cache_path)
+            {
+                Ok(model) => return model,
+                Err(e) => eprintln!("Failed to load cached model {:?}: {}", cache_path, e),
+            }
+        }
+
+        if std::fs::create_dir_all(&config.cache_dir).is_ok() {
+            let model = builder
+                .with_optimization_level(optimization_level(config.optimization_level))
+                .unwrap()
+                .with_optimized_model_path(&cache_path)
+                .unwrap()
+                .commit_from_memory(&model_data)
+                .unwrap();
+
+            return model;
+        }
+    }
+
+    let model = builder
+        .with_optimization_level(optimization_level(config.optimization_level))
+        .unwrap()
+        .commit_from_memory(&model_data)
+        .unwrap();
+
+    return model;
+}
+
+fn load_model(value: &str, ep: EP, config: &SessionConfig) -> Yolo {
+    let (model_metadata, data): (AI, Vec<u8>) = import_bq(value).unwrap();
 
     let len = model_metadata.classes.len() as u32;
-    let aimodel = Yolo::new(
+    Yolo::new(
         model_metadata.name,
         model_metadata.description,
         model_metadata.version,
         model_metadata.classes,
         model_metadata.input_height,
         model_metadata.input_height,
-        0.45,
+        CONFIDENCE_THRESHOLD,
         0.5,
         len,
         0,
         Task::from(model_metadata.task.as_str()),
-        import_model(&data, ep),
+        import_model(&data, ep, config),
+    )
+}
+
+pub fn set_model(value: String, ep: EP, config: &SessionConfig) {
+    let aimodel = load_model(&value, ep, config);
+    let (name, version) = (aimodel.name.clone(), aimodel.version);
+
+    *CURRENT_AI.lock().unwrap() = Some(aimodel);
+    *CURRENT_ENSEMBLE.lock().unwrap() = None;
+    WARM.store(false, Ordering::SeqCst);
+    set_loaded(name, version);
+}
+
+// Loads every model of the pipeline, from then on predictions come from the ensemble
+pub fn set_ensemble(pipeline: Pipeline, ep: EP, config: &SessionConfig) {
+    let models: Vec<Yolo> = pipeline
+        .get_model_paths()
+        .iter()
+        .map(|path| load_model(path, ep.clone(), config))
+        .collect();
+    let name = pipeline.name.clone();
+
+    *CURRENT_ENSEMBLE.lock().unwrap() = Some(Ensemble::new(pipeline, models));
+    WARM.store(false, Ordering::SeqCst);
+    set_loaded(name, 0.0);
+}
+
+// The embedder is kept apart from the detector, so both can be used at the same time
+pub fn set_embedder(value: String, ep: EP, config: &SessionConfig) {
+    let (model_metadata, data): (AI, Vec<u8>) = import_bq(&value).unwrap();
+
+    let embedder = Embedder::new(
+        model_metadata.name,
+        model_metadata.description,
+        model_metadata.version,
+        model_metadata.input_width,
+        model_metadata.input_height,
+        import_model(&data, ep, config),
     );
 
-    *CURRENT_AI.lock().unwrap() = aimodel;
+    *CURRENT_EMBEDDER.lock().unwrap() = Some(embedder);
 }
 
-pub fn detect_bbox_from_imgbuf(img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<XYXYc> {
-    match CURRENT_AI.lock().unwrap().run(&img) {
-        AIOutputs::ObjectDetection(boxes) => return boxes,
-        _ => {
-            panic!("Expected ObjectDetection output");
+pub fn get_embedder_name() -> Option<String> {
+    CURRENT_EMBEDDER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|embedder| embedder.name.clone())
+}
+
+pub fn embed_imgbuf(img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<f32> {
+    CURRENT_EMBEDDER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .expect("No embedder loaded, call set_embedder first")
+        .embed(img)
+}
+
+// Every analysis goes through the server from now on, if it answers
+pub fn set_remote(url: &str) -> Result<Vec<AI>, String> {
+    set_remote_with_api_key(url, None)
+}
+
+pub fn set_remote_with_api_key(url: &str, api_key: Option<&str>) -> Result<Vec<AI>, String> {
+    let client = RemoteClient::with_api_key(url, api_key)?;
+    client.readiness()?;
+    let models = client.list_models().unwrap_or_default();
+    *CURRENT_REMOTE.lock().unwrap() = Some(client);
+    Ok(models)
+}
+
+pub fn clear_remote() {
+    *CURRENT_REMOTE.lock().unwrap() = None;
+}
+
+pub fn get_remote_url() -> Option<String> {
+    CURRENT_REMOTE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|client| client.url.clone())
+}
+
+// None when there is no server, or it failed recently and the local model should be used.
+// The lock isn't held during the request, so a slow server doesn't block the other threads
+fn detect_bbox_from_imgbuf_remotely(img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Option<Vec<XYXYc>> {
+    let client = {
+        let current_remote = CURRENT_REMOTE.lock().unwrap();
+        let client = current_remote.as_ref()?;
+        if client.is_down() {
+            return None;
+        }
+        client.clone()
+    };
+    let result = client.detect_bbox_from_buf(image_buffer_to_jpg_buffer(img.clone()));
+
+    // The server may have been changed or cleared during the request
+    if let Some(current) = CURRENT_REMOTE.lock().unwrap().as_mut() {
+        if current.url == client.url {
+            current.record(result.is_ok());
+        }
+    }
+    match result {
+        Ok(boxes) => Some(boxes),
+        Err(e) => {
+            eprintln!("{} failed, using the local model: {}", client.url, e);
+            None
         }
     }
 }
 
-pub fn detect_bbox(file_path: &str) -> Vec<XYXYc> {
-    let img = open(file_path).unwrap().into_rgb8();
+fn set_loaded(name: String, version: f32) {
+    let ep = get_active_ep().map_or("CPU", |(ep, _)| ep);
+    *LOADED.write().unwrap() = Some(LoadedModel { name, version, ep });
+}
 
-    match CURRENT_AI.lock().unwrap().run(&img) {
-        AIOutputs::ObjectDetection(boxes) => return boxes,
-        _ => {
-            panic!("Expected ObjectDetection output");
-        }
+// The model answering predictions, the ensemble when there is one
+pub fn get_loaded_model() -> Option<LoadedModel> {
+    LOADED.read().unwrap().clone()
+}
+
+pub fn get_model_info() -> Option<(String, f32)> {
+    get_loaded_model().map(|model| (model.name, model.version))
+}
+
+// Like `detect_bbox_from_imgbuf`, but without panicking when there is no model, for the API
+pub fn try_detect_bbox_from_imgbuf(
+    img: &ImageBuffer<Rgb<u8>, Vec<u8>>,
+) -> Result<Vec<XYXYc>, String> {
+    let _queued = metrics::QueueGuard::enter();
+    let boxes = run_detection(img)?;
+    WARM.store(true, Ordering::SeqCst);
+    metrics::count_detections(boxes.iter().map(|bbox| bbox.label.as_str()));
+    Ok(boxes)
+}
+
+pub fn is_warm() -> bool {
+    WARM.load(Ordering::SeqCst)
+}
+
+// Runs a blank image through the model, so the first real prediction isn't the slow one
+pub fn warm_up() -> Result<(), String> {
+    if is_warm() {
+        return Ok(());
+    }
+    try_detect_bbox_from_imgbuf(&ImageBuffer::new(64, 64)).map(|_| ())
+}
+
+fn run_detection(img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Result<Vec<XYXYc>, String> {
+    if let Some(boxes) = detect_bbox_from_imgbuf_remotely(img) {
+        return Ok(boxes);
     }
-}
\ No newline at end of file
+
+    if let Some(ensemble) = CURRENT_ENSEMBLE.lock().unwrap().as_ref() {
+        return Ok(ensemble.run(img));
+    }
+
+    let current_ai = CURRENT_AI.lock().unwrap();
+    let aimodel = current_ai
+        .as_ref()
+        .ok_or("No model loaded, call set_model first".to_string())?;
+    match aimodel.run(&img) {
+        AIOutputs::ObjectDetection(boxes) => return Ok(boxes),
+        _ => return Err("Expected ObjectDetection output".to_string()),
+    }
+}
+
+pub fn detect_bbox_from_imgbuf(img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<XYXYc> {
+    try_detect_bbox_from_imgbuf(img).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn detect_bbox(file_path: &str) -> Vec<XYXYc> {
+    let img = open(file_path).unwrap().into_rgb8();
+    detect_bbox_from_imgbuf(&img)
+}
@@ -0,0 +1,809 @@
+// Long analyses for the REST API: a folder of images or a video, run in the background
+//
+// Jobs run one at a time on a worker thread, in the order they were created. Their state is
+// kept in jobs/jobs.json, so queued and interrupted jobs start again when the server restarts.
+// Folder results are appended to jobs/{id}.jsonl as they come, which lets an interrupted
+// folder resume where it stopped
+//
+// Paths sent to the API have to be inside the jobs root set with --jobs-root, or be uploads
+use super::abstractions::XYXYc;
+use super::config::{MotionConfig, SmoothingConfig};
+use super::import::{get_images_in_folder, is_supported_videofile};
+use super::inference::try_detect_bbox_from_imgbuf;
+use super::stream::StreamStats;
+use super::tracking::TrackSummary;
+use super::v1::{write_field, ApiError, ErrorResponse, VideoFormat};
+use super::video_file::{
+    get_output_path, predict_videofile_with_progress, FrameDetections, VideoSummary,
+};
+use super::zones::{ZoneConfig, ZoneCount};
+use axum::{
+    extract::{DefaultBodyLimit, FromRequest, Multipart, Path as UrlPath, Query, Request},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Local};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Once, RwLock};
+use std::time::Duration;
+use utoipa::{IntoParams, ToSchema};
+
+pub const JOBS_DIR: &str = "jobs";
+const STATE_FILE: &str = "jobs/jobs.json";
+// Progress is saved to the state file at most this often
+const SAVE_EVERY: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Completed | JobState::Failed | JobState::Cancelled)
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Folder,
+    Video,
+}
+
+/// # Fields
+/// - `every_n_frames` runs the model on one frame out of n, videos only
+/// - `zones` to count crossings in, videos only. Counted live at /counts while the job runs,
+///   when not set the video_zones.json file next to the video is used
+/// - `motion` skips inference frames without motion, videos only
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+#[serde(default)]
+pub struct JobOptions {
+    pub every_n_frames: usize,
+    pub smoothing: SmoothingConfig,
+    pub zones: Option<ZoneConfig>,
+    pub motion: MotionConfig,
+}
+
+impl Default for JobOptions {
+    fn default() -> Self {
+        Self {
+            every_n_frames: 1,
+            smoothing: SmoothingConfig::default(),
+            zones: None,
+            motion: MotionConfig::default(),
+        }
+    }
+}
+
+/// # Fields
+/// - `path` is a folder or a video on the server, uploads are saved under jobs/uploads first
+/// - `processed` and `total` count images for folders and frames for videos
+/// - `processed_at_start` were done before the job was resumed, the ETA leaves them out
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct Job {
+    pub id: String,
+    pub kind: JobKind,
+    pub path: String,
+    pub options: JobOptions,
+    pub state: JobState,
+    pub processed: u64,
+    #[serde(default)]
+    pub processed_at_start: u64,
+    pub total: u64,
+    pub created_at: DateTime<Local>,
+    pub started_at: Option<DateTime<Local>>,
+    pub finished_at: Option<DateTime<Local>>,
+    pub error: Option<String>,
+}
+
+impl Job {
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        self.processed as f32 / self.total as f32
+    }
+
+    // Seconds left at the speed so far, None until there is something to measure
+    pub fn eta_secs(&self) -> Option<f64> {
+        let done = self.processed.saturating_sub(self.processed_at_start);
+        if self.state != JobState::Running || done == 0 {
+            return None;
+        }
+        let elapsed = (Local::now() - self.started_at?).num_milliseconds() as f64 / 1000.0;
+        let per_item = elapsed / done as f64;
+        Some(per_item * self.total.saturating_sub(self.processed) as f64)
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct JobStatus {
+    #[serde(flatten)]
+    pub job: Job,
+    pub progress: f32,
+    pub eta_secs: Option<f64>,
+}
+
+impl From<Job> for JobStatus {
+    fn from(job: Job) -> Self {
+        Self {
+            progress: job.progress(),
+            eta_secs: job.eta_secs(),
+            job,
+        }
+    }
+}
+
+/// # Fields
+/// - `error` is set when the image couldn't be read or predicted, the job goes on with the next one
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct ImageResult {
+    pub file: String,
+    pub detections: Vec<XYXYc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ImageResult {
+    fn predict(file: String) -> Self {
+        let result = image::open(&file)
+            .map_err(|e| format!("Not an image: {}", e))
+            .and_then(|img| try_detect_bbox_from_imgbuf(&img.into_rgb8()));
+        match result {
+            Ok(detections) => Self {
+                file,
+                detections,
+                error: None,
+            },
+            Err(e) => Self {
+                file,
+                detections: Vec::new(),
+                error: Some(e),
+            },
+        }
+    }
+}
+
+/// What GET /v1/jobs/{id}/results answers
+/// # Fields
+/// - `images` is filled for folders, also while the job is running
+/// - `video`, `tracks`, `zones` and `stats` are filled for videos once they are completed
+#[derive(Serialize, Deserialize, ToSchema, Default)]
+pub struct JobResults {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tracks: Vec<TrackSummary>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<ZoneCount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<StreamStats>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct JobRequest {
+    pub path: String,
+    #[serde(default)]
+    pub options: JobOptions,
+}
+
+/// Query of GET /v1/jobs/{id}/download
+/// # Fields
+/// - `format` is the annotated video, or the boxes of every frame as JSON or NDJSON
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct DownloadQuery {
+    pub format: VideoFormat,
+}
+
+impl Default for DownloadQuery {
+    fn default() -> Self {
+        Self {
+            format: VideoFormat::Mp4,
+        }
+    }
+}
+
+// Multipart alternative to JobRequest, only used to describe it in the OpenAPI document
+#[derive(ToSchema)]
+pub struct JobUpload {
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+    // JobOptions as JSON
+    pub options: Option<String>,
+}
+
+struct JobQueue {
+    jobs: Vec<Job>,
+    cancel: HashMap<String, Arc<AtomicBool>>,
+}
+
+static JOBS: Lazy<Mutex<JobQueue>> = Lazy::new(|| {
+    Mutex::new(JobQueue {
+        jobs: Vec::new(),
+        cancel: HashMap::new(),
+    })
+});
+static WORKER: Once = Once::new();
+static JOBS_ROOT: Lazy<RwLock<Option<PathBuf>>> = Lazy::new(|| RwLock::new(None));
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+fn results_path(id: &str) -> PathBuf {
+    Path::new(JOBS_DIR).join(format!("{}.jsonl", id))
+}
+
+fn summary_path(id: &str) -> PathBuf {
+    Path::new(JOBS_DIR).join(format!("{}_summary.json", id))
+}
+
+fn frames_path(id: &str) -> PathBuf {
+    Path::new(JOBS_DIR).join(format!("{}_frames.jsonl", id))
+}
+
+// None leaves only the uploads
+pub fn set_jobs_root(root: Option<&str>) {
+    *JOBS_ROOT.write().unwrap() = root.map(PathBuf::from);
+}
+
+// The path without `..` or links, when it's inside the jobs root or the uploads
+fn check_path(path: &str) -> Result<PathBuf, ApiError> {
+    let not_found = || ApiError::bad_request(format!("{} is not a folder or a supported video", path));
+    let canonical = Path::new(path).canonicalize().map_err(|_| not_found())?;
+    let uploads = Path::new(JOBS_DIR).join("uploads").canonicalize().ok();
+    let root = JOBS_ROOT
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|root| root.canonicalize().ok());
+    if [uploads, root].iter().flatten().any(|allowed| canonical.starts_with(allowed)) {
+        Ok(canonical)
+    } else {
+        Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            format!("{} is outside the jobs root", path),
+        ))
+    }
+}
+
+// Uploads are only read by their job, so they go away once it finishes. The predicted
+// video is written next to the upload and stays for /download
+fn remove_upload(path: &str) {
+    let uploads = Path::new(JOBS_DIR).join("uploads").canonicalize();
+    let (Ok(uploads), Ok(path)) = (uploads, Path::new(path).canonicalize()) else {
+        return;
+    };
+    if path.starts_with(uploads) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn new_id() -> String {
+    format!(
+        "{:x}{:03x}",
+        Local::now().timestamp_millis(),
+        NEXT_ID.fetch_add(1, Ordering::SeqCst) % 0x1000
+    )
+}
+
+fn save_state(jobs: &[Job]) {
+    let content = serde_json::to_string_pretty(jobs).unwrap();
+    // Written next to the state file first, so a crash doesn't leave it half written
+    let tmp = format!("{}.tmp", STATE_FILE);
+    if std::fs::write(&tmp, content).is_ok() {
+        let _ = std::fs::rename(&tmp, STATE_FILE);
+    }
+}
+
+fn load_state() -> Vec<Job> {
+    let mut jobs: Vec<Job> = std::fs::read_to_string(STATE_FILE)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    // Jobs that were running when the server stopped go back to the queue
+    for job in jobs.iter_mut() {
+        if job.state == JobState::Running {
+            job.state = JobState::Queued;
+        }
+    }
+    jobs
+}
+
+fn update_job<F: FnOnce(&mut Job)>(id: &str, f: F) {
+    let mut queue = JOBS.lock().unwrap();
+    if let Some(job) = queue.jobs.iter_mut().find(|job| job.id == id) {
+        f(job);
+    }
+    save_state(&queue.jobs);
+}
+
+// Loads the state file and starts the worker, only the first call does anything
+pub fn start_jobs() {
+    WORKER.call_once(|| {
+        std::fs::create_dir_all(Path::new(JOBS_DIR).join("uploads")).unwrap();
+        let mut queue = JOBS.lock().unwrap();
+        queue.jobs = load_state();
+        for job in &queue.jobs {
+            if !job.state.is_finished() {
+                eprintln!("Resuming job {} ({})", job.id, job.path);
+            }
+        }
+        drop(queue);
+        std::thread::spawn(worker);
+    });
+}
+
+fn worker() {
+    loop {
+        let next = {
+            let mut queue = JOBS.lock().unwrap();
+            let next = queue
+                .jobs
+                .iter_mut()
+                .find(|job| job.state == JobState::Queued)
+                .map(|job| {
+                    job.state = JobState::Running;
+                    job.started_at = Some(Local::now());
+                    job.clone()
+                });
+            if let Some(job) = &next {
+                let cancel = Arc::new(AtomicBool::new(false));
+                queue.cancel.insert(job.id.clone(), cancel.clone());
+                save_state(&queue.jobs);
+                next.map(|job| (job, cancel))
+            } else {
+                None
+            }
+        };
+
+        let Some((job, cancel)) = next else {
+            std::thread::sleep(Duration::from_millis(500));
+            continue;
+        };
+
+        // A panic in the decoder or the model fails the job instead of the worker
+        let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| match job.kind {
+            JobKind::Folder => run_folder(&job, &cancel),
+            JobKind::Video => run_video(&job, &cancel),
+        }))
+        .unwrap_or_else(|panic| {
+            Err(panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or(panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or("The job crashed".to_string()))
+        });
+
+        JOBS.lock().unwrap().cancel.remove(&job.id);
+        update_job(&job.id, |job| {
+            job.finished_at = Some(Local::now());
+            match outcome {
+                Ok(()) if cancel.load(Ordering::SeqCst) => job.state = JobState::Cancelled,
+                Ok(()) => job.state = JobState::Completed,
+                Err(e) => {
+                    job.state = JobState::Failed;
+                    job.error = Some(e);
+                }
+            }
+        });
+        remove_upload(&job.path);
+    }
+}
+
+// Saves the progress every SAVE_EVERY, and says if the job should go on
+struct Progress<'a> {
+    id: &'a str,
+    cancel: &'a AtomicBool,
+    saved: std::time::Instant,
+}
+
+impl<'a> Progress<'a> {
+    fn update(&mut self, processed: u64, total: u64) -> bool {
+        let force = processed == total;
+        let mut queue = JOBS.lock().unwrap();
+        if let Some(job) = queue.jobs.iter_mut().find(|job| job.id == self.id) {
+            job.processed = processed;
+            job.total = total;
+        }
+        if force || self.saved.elapsed() > SAVE_EVERY {
+            save_state(&queue.jobs);
+            self.saved = std::time::Instant::now();
+        }
+        !self.cancel.load(Ordering::SeqCst)
+    }
+}
+
+fn run_folder(job: &Job, cancel: &AtomicBool) -> Result<(), String> {
+    let paths = get_images_in_folder(Path::new(&job.path)).map_err(|e| e.to_string())?;
+    let total = paths.len() as u64;
+
+    // Images already in the results file were done before a restart
+    let done = std::fs::File::open(results_path(&job.id))
+        .map(|file| BufReader::new(file).lines().count())
+        .unwrap_or(0);
+    let mut results = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(results_path(&job.id))
+        .map_err(|e| e.to_string())?;
+
+    let mut progress = Progress {
+        id: &job.id,
+        cancel,
+        saved: std::time::Instant::now(),
+    };
+    update_job(&job.id, |job| job.processed_at_start = done as u64);
+    progress.update(done as u64, total);
+    for (i, path) in paths.iter().enumerate().skip(done) {
+        let result = ImageResult::predict(path.to_string_lossy().into_owned());
+        writeln!(results, "{}", serde_json::to_string(&result).unwrap()).map_err(|e| e.to_string())?;
+        if !progress.update(i as u64 + 1, total) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn run_video(job: &Job, cancel: &AtomicBool) -> Result<(), String> {
+    let mut progress = Progress {
+        id: &job.id,
+        cancel,
+        saved: std::time::Instant::now(),
+    };
+    let mut frames = std::fs::File::create(frames_path(&job.id)).map_err(|e| e.to_string())?;
+    let summary = predict_videofile_with_progress(
+        &job.path,
+        job.options.every_n_frames,
+        &job.options.smoothing,
+        &job.options.motion,
+        job.options.zones.clone(),
+        move |frame| {
+            let _ = writeln!(frames, "{}", serde_json::to_string(&frame).unwrap());
+        },
+        |done, total| progress.update(done, total),
+    );
+    if let Some(summary) = summary {
+        let content = serde_json::to_string(&summary).unwrap();
+        std::fs::write(summary_path(&job.id), content).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+pub fn create_job(path: &str, options: JobOptions) -> Result<Job, ApiError> {
+    start_jobs();
+    let checked = check_path(path)?;
+    let kind = if checked.is_dir() {
+        JobKind::Folder
+    } else if checked.is_file() && is_supported_videofile(path) {
+        JobKind::Video
+    } else {
+        return Err(ApiError::bad_request(format!(
+            "{} is not a folder or a supported video",
+            path
+        )));
+    };
+
+    let job = Job {
+        id: new_id(),
+        kind,
+        path: checked.to_string_lossy().into_owned(),
+        options,
+        state: JobState::Queued,
+        processed: 0,
+        processed_at_start: 0,
+        total: 0,
+        created_at: Local::now(),
+        started_at: None,
+        finished_at: None,
+        error: None,
+    };
+    let mut queue = JOBS.lock().unwrap();
+    queue.jobs.push(job.clone());
+    save_state(&queue.jobs);
+    Ok(job)
+}
+
+pub fn get_job(id: &str) -> Option<Job> {
+    start_jobs();
+    JOBS.lock().unwrap().jobs.iter().find(|job| job.id == id).cloned()
+}
+
+pub fn list_jobs() -> Vec<Job> {
+    start_jobs();
+    JOBS.lock().unwrap().jobs.clone()
+}
+
+// Doesn't start the worker, so reading it has no side effects. 0 until the jobs are started
+pub fn queued_jobs() -> usize {
+    JOBS.lock()
+        .unwrap()
+        .jobs
+        .iter()
+        .filter(|job| job.state == JobState::Queued)
+        .count()
+}
+
+// Queued jobs are cancelled right away, running ones stop after the current image or frame
+pub fn cancel_job(id: &str) -> Result<Job, ApiError> {
+    let mut queue = JOBS.lock().unwrap();
+    let flag = queue.cancel.get(id).cloned();
+    let job = queue
+        .jobs
+        .iter_mut()
+        .find(|job| job.id == id)
+        .ok_or(ApiError::not_found(format!("There is no job {}", id)))?;
+    match job.state {
+        JobState::Queued => {
+            job.state = JobState::Cancelled;
+            job.finished_at = Some(Local::now());
+            remove_upload(&job.path);
+        }
+        JobState::Running => {
+            if let Some(flag) = flag {
+                flag.store(true, Ordering::SeqCst);
+            }
+        }
+        state => {
+            return Err(ApiError::new(
+                StatusCode::CONFLICT,
+                format!("The job is already {:?}", state).to_lowercase(),
+            ))
+        }
+    }
+    let job = job.clone();
+    save_state(&queue.jobs);
+    Ok(job)
+}
+
+pub fn get_results(job: &Job) -> JobResults {
+    match job.kind {
+        JobKind::Folder => JobResults {
+            images: std::fs::File::open(results_path(&job.id))
+                .map(|file| {
+                    BufReader::new(file)
+                        .lines()
+                        .filter_map(|line| serde_json::from_str(&line.ok()?).ok())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            ..JobResults::default()
+        },
+        JobKind::Video => {
+            let summary: Option<VideoSummary> = std::fs::read_to_string(summary_path(&job.id))
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok());
+            let summary = summary.unwrap_or_default();
+            JobResults {
+                video: (job.state == JobState::Completed).then(|| get_output_path(&job.path)),
+                tracks: summary.tracks,
+                zones: summary.zones,
+                stats: (job.state == JobState::Completed).then_some(summary.stats),
+                ..JobResults::default()
+            }
+        }
+    }
+}
+
+// The file of a multipart upload is saved under jobs/uploads, the other fields are options
+async fn save_upload(mut multipart: Multipart) -> Result<JobRequest, ApiError> {
+    start_jobs();
+    let mut path = None;
+    let mut options = JobOptions::default();
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?
+    {
+        if field.name() == Some("options") {
+            let text = field.text().await.map_err(|e| ApiError::bad_request(e.body_text()))?;
+            options = serde_json::from_str(&text)
+                .map_err(|e| ApiError::bad_request(format!("Invalid options: {}", e)))?;
+            continue;
+        }
+        // Only the file name is kept, so the upload can't be written outside jobs/uploads
+        let file_name = field
+            .file_name()
+            .and_then(|name| Path::new(name).file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .ok_or(ApiError::bad_request("The uploaded file has no name"))?;
+        let target = Path::new(JOBS_DIR)
+            .join("uploads")
+            .join(format!("{}_{}", new_id(), file_name))
+            .to_string_lossy()
+            .into_owned();
+        write_field(&mut field, &target).await?;
+        path = Some(target);
+    }
+    Ok(JobRequest {
+        path: path.ok_or(ApiError::bad_request("The form has no file"))?,
+        options,
+    })
+}
+
+// POST /v1/jobs, a JSON body with a path on the server or a multipart upload
+#[utoipa::path(
+    post,
+    path = "/v1/jobs",
+    tag = "jobs",
+    request_body(content(
+        (JobRequest = "application/json"),
+        (JobUpload = "multipart/form-data"),
+    )),
+    responses(
+        (status = 202, description = "The job was queued", body = JobStatus),
+        (status = 400, description = "The path is not a folder or a video", body = ErrorResponse),
+        (status = 403, description = "The path is outside the jobs root", body = ErrorResponse),
+    )
+)]
+pub async fn post_job(request: Request) -> Result<(StatusCode, Json<JobStatus>), ApiError> {
+    let is_multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+
+    let job_request = if is_multipart {
+        let multipart = Multipart::from_request(request, &()).await?;
+        save_upload(multipart).await?
+    } else {
+        let Json(job_request) = Json::<JobRequest>::from_request(request, &())
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        job_request
+    };
+
+    let job = create_job(&job_request.path, job_request.options).inspect_err(|_| {
+        if is_multipart {
+            remove_upload(&job_request.path);
+        }
+    })?;
+    Ok((StatusCode::ACCEPTED, Json(job.into())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/jobs",
+    tag = "jobs",
+    responses((status = 200, description = "Every job, oldest first", body = Vec<JobStatus>))
+)]
+pub async fn get_jobs() -> Json<Vec<JobStatus>> {
+    Json(list_jobs().into_iter().map(|job| job.into()).collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path)),
+    responses(
+        (status = 200, description = "State, progress and ETA", body = JobStatus),
+        (status = 404, description = "There is no job with that id", body = ErrorResponse),
+    )
+)]
+pub async fn get_status(UrlPath(id): UrlPath<String>) -> Result<Json<JobStatus>, ApiError> {
+    get_job(&id)
+        .map(|job| Json(job.into()))
+        .ok_or(ApiError::not_found(format!("There is no job {}", id)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/v1/jobs/{id}",
+    tag = "jobs",
+    params(("id" = String, Path)),
+    responses(
+        (status = 200, description = "The job was cancelled or will stop soon", body = JobStatus),
+        (status = 404, description = "There is no job with that id", body = ErrorResponse),
+        (status = 409, description = "The job already finished", body = ErrorResponse),
+    )
+)]
+pub async fn delete_job(UrlPath(id): UrlPath<String>) -> Result<Json<JobStatus>, ApiError> {
+    Ok(Json(cancel_job(&id)?.into()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{id}/results",
+    tag = "jobs",
+    params(("id" = String, Path)),
+    responses(
+        (status = 200, description = "Results so far for folders, final results for videos", body = JobResults),
+        (status = 404, description = "There is no job with that id", body = ErrorResponse),
+        (status = 409, description = "The video is still being processed", body = ErrorResponse),
+    )
+)]
+pub async fn results(UrlPath(id): UrlPath<String>) -> Result<Json<JobResults>, ApiError> {
+    let job = get_job(&id).ok_or(ApiError::not_found(format!("There is no job {}", id)))?;
+    if job.kind == JobKind::Video && !job.state.is_finished() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "The video is still being processed, check GET /v1/jobs/{id} for progress",
+        ));
+    }
+    Ok(Json(tokio::task::spawn_blocking(move || get_results(&job)).await.unwrap()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/jobs/{id}/download",
+    tag = "jobs",
+    params(("id" = String, Path), DownloadQuery),
+    responses(
+        (status = 200, description = "The annotated video, or the boxes of every frame", content(
+            (String = "video/mp4"),
+            (Vec<FrameDetections> = "application/json"),
+            (FrameDetections = "application/x-ndjson"),
+        )),
+        (status = 400, description = "The job is a folder, its results are at /results", body = ErrorResponse),
+        (status = 404, description = "There is no job with that id", body = ErrorResponse),
+        (status = 409, description = "The video is not completed", body = ErrorResponse),
+    )
+)]
+pub async fn download(
+    UrlPath(id): UrlPath<String>,
+    Query(query): Query<DownloadQuery>,
+) -> Result<Response, ApiError> {
+    let job = get_job(&id).ok_or(ApiError::not_found(format!("There is no job {}", id)))?;
+    if job.kind != JobKind::Video {
+        return Err(ApiError::bad_request(
+            "Only videos can be downloaded, the results of folders are at /v1/jobs/{id}/results",
+        ));
+    }
+    if job.state != JobState::Completed {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("The video is {:?}, it can only be downloaded once completed", job.state).to_lowercase(),
+        ));
+    }
+
+    let read = |path: PathBuf| async move {
+        tokio::fs::read(&path)
+            .await
+            .map_err(|e| ApiError::internal(format!("Failed to read {}: {}", path.display(), e)))
+    };
+    match query.format {
+        VideoFormat::Mp4 => {
+            let data = read(PathBuf::from(get_output_path(&job.path))).await?;
+            Ok(([(header::CONTENT_TYPE, "video/mp4")], data).into_response())
+        }
+        VideoFormat::Json => {
+            let data = read(frames_path(&job.id)).await?;
+            let frames: Vec<FrameDetections> = String::from_utf8_lossy(&data)
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect();
+            Ok(Json(frames).into_response())
+        }
+        VideoFormat::Ndjson => {
+            let data = read(frames_path(&job.id)).await?;
+            Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], data).into_response())
+        }
+    }
+}
+
+pub fn router() -> Router {
+    Router::new()
+        // Uploads can be whole videos, so they aren't limited like the other routes
+        .route(
+            "/",
+            get(get_jobs).post(post_job).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/{id}", get(get_status).delete(delete_job))
+        .route("/{id}/results", get(results))
+        .route("/{id}/download", get(download))
+}
@@ -0,0 +1,200 @@
+// Capture metadata read from the EXIF of each image, the file modification time is the fallback
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use exif::{Exif, In, Reader, Tag, Value};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Everything we know about when, where and how an image was taken
+/// # Fields
+/// - `time_source` is "exif" or "file", depending on where `capture_time` came from
+/// - `latitude` and `longitude` are in decimal degrees, negative for S and W
+/// - `temperature` is in °C
+/// - `trigger` is the trigger mode reported by the camera, e.g. "motion" or "timelapse"
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ImgMetadata {
+    pub capture_time: Option<NaiveDateTime>,
+    pub time_source: String,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+    pub temperature: Option<f64>,
+    pub trigger: Option<String>,
+}
+
+pub const METADATA_HEADERS: [&'static str; 9] = [
+    "Capture Time",
+    "Time Source",
+    "Make",
+    "Model",
+    "Latitude",
+    "Longitude",
+    "Altitude",
+    "Temperature",
+    "Trigger",
+];
+
+// Camera traps that don't use the EXIF Temperature tag usually write it in the description
+static TEMPERATURE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(-?\d+(?:\.\d+)?)\s*°?\s*C\b").unwrap());
+static TRIGGER_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(motion|time\s?lapse|pir|manual|trigger)\b").unwrap());
+
+impl ImgMetadata {
+    pub fn read(file_path: &Path) -> Self {
+        let mut metadata = match read_exif(file_path) {
+            Some(exif) => from_exif(&exif),
+            None => ImgMetadata::default(),
+        };
+
+        if metadata.capture_time.is_some() {
+            metadata.time_source = "exif".to_string();
+        } else {
+            metadata.capture_time = modification_time(file_path);
+            metadata.time_source = "file".to_string();
+        }
+        metadata
+    }
+
+    // Same order as METADATA_HEADERS
+    pub fn to_record(&self) -> Vec<String> {
+        fn opt<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+        }
+        vec![
+            opt(&self.capture_time),
+            self.time_source.clone(),
+            opt(&self.make),
+            opt(&self.model),
+            opt(&self.latitude),
+            opt(&self.longitude),
+            opt(&self.altitude),
+            opt(&self.temperature),
+            opt(&self.trigger),
+        ]
+    }
+}
+
+fn modification_time(file_path: &Path) -> Option<NaiveDateTime> {
+    let modified = std::fs::metadata(file_path).ok()?.modified().ok()?;
+    Some(DateTime::<Local>::from(modified).naive_local())
+}
+
+fn read_exif(file_path: &Path) -> Option<Exif> {
+    let file = File::open(file_path).ok()?;
+    Reader::new()
+        .read_from_container(&mut BufReader::new(&file))
+        .ok()
+}
+
+fn from_exif(exif: &Exif) -> ImgMetadata {
+    // Free text fields, where some cameras store temperature and trigger info
+    let text = [ascii(exif, Tag::ImageDescription), user_comment(exif)]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let temperature = match exif.get_field(Tag::Temperature, In::PRIMARY) {
+        Some(field) => match &field.value {
+            Value::SRational(v) if !v.is_empty() => Some(v[0].to_f64()),
+            _ => None,
+        },
+        None => TEMPERATURE_REGEX
+            .captures(&text)
+            .and_then(|c| c[1].parse::<f64>().ok()),
+    };
+
+    let trigger = TRIGGER_REGEX
+        .captures(&text)
+        .map(|c| c[1].to_lowercase().replace(' ', ""));
+
+    ImgMetadata {
+        capture_time: capture_time(exif),
+        time_source: String::new(),
+        make: ascii(exif, Tag::Make),
+        model: ascii(exif, Tag::Model),
+        latitude: gps_coord(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+        longitude: gps_coord(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+        altitude: altitude(exif),
+        temperature,
+        trigger,
+    }
+}
+
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(v) => v
+            .first()
+            .map(|bytes| String::from_utf8_lossy(bytes).trim().to_string())
+            .filter(|s| !s.is_empty()),
+        _ => None,
+    }
+}
+
+// The first 8 bytes of a UserComment are the character code
+fn user_comment(exif: &Exif) -> Option<String> {
+    match &exif.get_field(Tag::UserComment, In::PRIMARY)?.value {
+        Value::Undefined(bytes, _) if bytes.len() > 8 => {
+            let comment = String::from_utf8_lossy(&bytes[8..])
+                .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                .to_string();
+            Some(comment).filter(|s| !s.is_empty())
+        }
+        _ => None,
+    }
+}
+
+fn capture_time(exif: &Exif) -> Option<NaiveDateTime> {
+    for tag in [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime] {
+        let Some(field) = exif.get_field(tag, In::PRIMARY) else {
+            continue;
+        };
+        if let Value::Ascii(v) = &field.value {
+            let Some(dt) = v.first().and_then(|data| exif::DateTime::from_ascii(data).ok()) else {
+                continue;
+            };
+            let time = NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32)
+                .and_then(|date| {
+                    date.and_hms_opt(dt.hour as u32, dt.minute as u32, dt.second as u32)
+                });
+            if time.is_some() {
+                return time;
+            }
+        }
+    }
+    None
+}
+
+// GPS coordinates are stored as degrees, minutes and seconds
+fn gps_coord(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+    let value = match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(v) if v.len() >= 3 => {
+            v[0].to_f64() + v[1].to_f64() / 60.0 + v[2].to_f64() / 3600.0
+        }
+        _ => return None,
+    };
+    let sign = match ascii(exif, ref_tag) {
+        Some(r) if r == negative_ref => -1.0,
+        _ => 1.0,
+    };
+    Some(sign * value)
+}
+
+fn altitude(exif: &Exif) -> Option<f64> {
+    let value = match &exif.get_field(Tag::GPSAltitude, In::PRIMARY)?.value {
+        Value::Rational(v) if !v.is_empty() => v[0].to_f64(),
+        _ => return None,
+    };
+    // A reference of 1 means below sea level
+    let below = exif
+        .get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        == Some(1);
+    Some(if below { -value } else { value })
+}
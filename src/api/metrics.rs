@@ -0,0 +1,207 @@
+// Prometheus metrics of the REST API, served at /metrics
+//
+// Counters live in process-wide statics, so predictions made outside the API
+// (jobs, videos) are counted too. Gauges about the model are read when scraped
+use super::inference::get_loaded_model;
+use super::jobs::queued_jobs;
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Counter, Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::time::Instant;
+
+// Stages of a prediction, as labels of STAGE_SECONDS
+pub const DECODE: &str = "decode";
+pub const INFERENCE: &str = "inference";
+pub const POST_PROCESS: &str = "post_process";
+
+static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new("boquilahub_http_requests_total", "HTTP requests by route, method and status"),
+        &["route", "method", "status"],
+    )
+    .unwrap()
+});
+
+static STAGE_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    HistogramVec::new(
+        HistogramOpts::new(
+            "boquilahub_prediction_stage_seconds",
+            "Time spent decoding images, running the model and post-processing its output",
+        )
+        .buckets(vec![0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]),
+        &["stage"],
+    )
+    .unwrap()
+});
+
+static DETECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new("boquilahub_detections_total", "Boxes predicted by class"),
+        &["class"],
+    )
+    .unwrap()
+});
+
+static GATED_FRAMES: Lazy<IntCounterVec> = Lazy::new(|| {
+    IntCounterVec::new(
+        Opts::new(
+            "boquilahub_video_frames_total",
+            "Inference frames of videos, by whether the motion gate let the model run",
+        ),
+        &["result"],
+    )
+    .unwrap()
+});
+
+static CPU_SAVED: Lazy<Counter> = Lazy::new(|| {
+    Counter::new(
+        "boquilahub_motion_cpu_saved_seconds_total",
+        "Estimated inference time saved by the motion gate",
+    )
+    .unwrap()
+});
+
+static MODEL_INFO: Lazy<IntGaugeVec> = Lazy::new(|| {
+    IntGaugeVec::new(
+        Opts::new("boquilahub_model_info", "Always 1, labelled with the loaded model and EP"),
+        &["model", "version", "ep"],
+    )
+    .unwrap()
+});
+
+static INFERENCE_QUEUE: Lazy<IntGauge> = Lazy::new(|| {
+    IntGauge::new(
+        "boquilahub_inference_queue_depth",
+        "Predictions waiting for the model or running on it",
+    )
+    .unwrap()
+});
+
+static JOBS_QUEUED: Lazy<IntGauge> = Lazy::new(|| {
+    IntGauge::new("boquilahub_jobs_queued", "Background jobs waiting to start").unwrap()
+});
+
+static RESIDENT_MEMORY: Lazy<IntGauge> = Lazy::new(|| {
+    IntGauge::new(
+        "boquilahub_resident_memory_bytes",
+        "Physical memory used by the process, on every platform",
+    )
+    .unwrap()
+});
+
+static REGISTRY: Lazy<Registry> = Lazy::new(|| {
+    let registry = Registry::new();
+    registry.register(Box::new(HTTP_REQUESTS.clone())).unwrap();
+    registry.register(Box::new(STAGE_SECONDS.clone())).unwrap();
+    registry.register(Box::new(DETECTIONS.clone())).unwrap();
+    registry.register(Box::new(GATED_FRAMES.clone())).unwrap();
+    registry.register(Box::new(CPU_SAVED.clone())).unwrap();
+    registry.register(Box::new(MODEL_INFO.clone())).unwrap();
+    registry.register(Box::new(INFERENCE_QUEUE.clone())).unwrap();
+    registry.register(Box::new(JOBS_QUEUED.clone())).unwrap();
+    registry.register(Box::new(RESIDENT_MEMORY.clone())).unwrap();
+    // CPU time, open files and friends, only implemented on Linux
+    #[cfg(target_os = "linux")]
+    registry
+        .register(Box::new(prometheus::process_collector::ProcessCollector::for_self()))
+        .unwrap();
+    registry
+});
+
+pub fn observe_stage(stage: &str, start: Instant) {
+    STAGE_SECONDS
+        .with_label_values(&[stage])
+        .observe(start.elapsed().as_secs_f64());
+}
+
+pub fn count_detections<'a>(labels: impl Iterator<Item = &'a str>) {
+    for label in labels {
+        DETECTIONS.with_label_values(&[label]).inc();
+    }
+}
+
+// `saved_secs` is the average inference time, added when the gate skipped the frame
+pub fn count_gated_frame(inferred: bool, saved_secs: f64) {
+    if inferred {
+        GATED_FRAMES.with_label_values(&["inferred"]).inc();
+    } else {
+        GATED_FRAMES.with_label_values(&["skipped"]).inc();
+        CPU_SAVED.inc_by(saved_secs);
+    }
+}
+
+// Counts a prediction in the queue until it's dropped
+pub struct QueueGuard;
+
+impl QueueGuard {
+    pub fn enter() -> Self {
+        INFERENCE_QUEUE.inc();
+        QueueGuard
+    }
+}
+
+impl Drop for QueueGuard {
+    fn drop(&mut self) {
+        INFERENCE_QUEUE.dec();
+    }
+}
+
+// Axum middleware, routes are labelled with their pattern so ids don't make new series
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched".to_string(), |path| path.as_str().to_string());
+    let method = request.method().to_string();
+    let response = next.run(request).await;
+    HTTP_REQUESTS
+        .with_label_values(&[&route, &method, response.status().as_str()])
+        .inc();
+    response
+}
+
+// Text exposition format, what Prometheus scrapes
+pub fn render() -> Result<String, String> {
+    MODEL_INFO.reset();
+    if let Some(model) = get_loaded_model() {
+        MODEL_INFO
+            .with_label_values(&[&model.name, &model.version.to_string(), model.ep])
+            .set(1);
+    }
+    JOBS_QUEUED.set(queued_jobs() as i64);
+    if let Some(stats) = memory_stats::memory_stats() {
+        RESIDENT_MEMORY.set(stats.physical_mem as i64);
+    }
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .map_err(|e| e.to_string())?;
+    String::from_utf8(buffer).map_err(|e| e.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "monitoring",
+    responses((status = 200, description = "Prometheus text format", body = String, content_type = "text/plain"))
+)]
+pub async fn metrics() -> Response {
+    match tokio::task::spawn_blocking(render).await {
+        Ok(Ok(text)) => (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+            text,
+        )
+            .into_response(),
+        Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, e).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
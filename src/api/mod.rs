@@ -11,4 +11,22 @@ pub mod render;
 pub mod utils;
 pub mod import;
 pub mod stream;
-pub mod models;
\ No newline at end of file
+pub mod models;
+pub mod config;
+pub mod ensemble;
+pub mod triage;
+pub mod sequence;
+pub mod metadata;
+pub mod embeddings;
+pub mod tracking;
+pub mod zones;
+pub mod motion;
+pub mod smoothing;
+pub mod remote;
+pub mod remote_pool;
+pub mod v1;
+pub mod auth;
+pub mod jobs;
+pub mod openapi;
+pub mod metrics;
+pub mod health;
\ No newline at end of file
@@ -0,0 +1,85 @@
+use image::{
+    imageops::{resize, FilterType},
+    ImageBuffer, Rgb,
+};
+use ndarray::{Array, Ix4};
+use ort::{inputs, session::Session};
+
+// Feature extractor, turns an image into a vector so similar images end up close to each other
+pub struct Embedder {
+    pub name: String,
+    pub description: String,
+    pub version: f32,
+    pub input_width: u32,
+    pub input_height: u32,
+    pub session: Session,
+}
+
+impl Embedder {
+    pub fn new(
+        name: String,
+        description: String,
+        version: f32,
+        input_width: u32,
+        input_height: u32,
+        session: Session,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            version,
+            input_width,
+            input_height,
+            session,
+        }
+    }
+
+    fn prepare_input_from_imgbuf(&self, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Array<f32, Ix4> {
+        let resized = resize(
+            img,
+            self.input_width,
+            self.input_height,
+            FilterType::Triangle,
+        );
+
+        let mut input = Array::zeros((1, 3, self.input_height as usize, self.input_width as usize));
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            let x_u = x as usize;
+            let y_u = y as usize;
+            input[[0, 0, y_u, x_u]] = (pixel[0] as f32) / 255.0;
+            input[[0, 1, y_u, x_u]] = (pixel[1] as f32) / 255.0;
+            input[[0, 2, y_u, x_u]] = (pixel[2] as f32) / 255.0;
+        }
+        input
+    }
+
+    // The first output of the model, flattened and L2 normalized,
+    // so the dot product between two embeddings is their cosine similarity
+    pub fn embed(&self, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<f32> {
+        let input = self.prepare_input_from_imgbuf(img);
+        let input_name = self.session.inputs[0].name.clone();
+        let output_name = self.session.outputs[0].name.as_str();
+
+        let outputs = self
+            .session
+            .run(inputs![input_name => input.view()].unwrap())
+            .unwrap();
+
+        let features: Vec<f32> = outputs[output_name]
+            .try_extract_tensor::<f32>()
+            .unwrap()
+            .iter()
+            .copied()
+            .collect();
+
+        normalize(features)
+    }
+}
+
+pub fn normalize(mut vector: Vec<f32>) -> Vec<f32> {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+    vector
+}
@@ -1,12 +1,18 @@
 #![allow(dead_code)]
+pub mod embedder;
 pub mod yolo;
+pub use embedder::Embedder;
 pub use yolo::Yolo;
 use super::{abstractions::*};
 
+// Boxes below this confidence are dropped by the detector, before anything else sees them
+pub const CONFIDENCE_THRESHOLD: f32 = 0.45;
+
 pub enum Task {
     Classify,
     Segment,
     Detect,
+    Embed,
 }
 
 impl From<&str> for Task {
@@ -15,6 +21,7 @@ impl From<&str> for Task {
             "detect" => Task::Detect,
             "classify" => Task::Classify,
             "segment" => Task::Segment,
+            "embed" => Task::Embed,
             _ => Task::Detect, // Default to Detect if unknown
         }
     }
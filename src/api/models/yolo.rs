@@ -2,6 +2,7 @@ use super::*;
 use crate::api::{
     abstractions::{BoundingBoxTrait, XYXY},
     bq::import_bq,
+    metrics,
 };
 use image::{
     imageops::{resize, FilterType},
@@ -12,6 +13,7 @@ use ort::{
     inputs,
     session::{builder::GraphOptimizationLevel, Session},
 };
+use std::time::Instant;
 
 pub struct Yolo {
     pub name: String,
@@ -75,7 +77,7 @@ impl Yolo {
             vec!["animal".to_string()],
             1024,
             1024,
-            0.45,
+            CONFIDENCE_THRESHOLD,
             0.5,
             1,
             0,
@@ -182,8 +184,12 @@ impl Yolo {
         let (input, img_width, img_height) = self.prepare_input_from_imgbuf(img);
         match self.task {
             Task::Detect => {
+                let start = Instant::now();
                 let output = self.run_detect(&input);
+                metrics::observe_stage(metrics::INFERENCE, start);
+                let start = Instant::now();
                 let boxes = self.process_detect_output(&output, img_width, img_height);
+                metrics::observe_stage(metrics::POST_PROCESS, start);
                 return AIOutputs::ObjectDetection(boxes);
             }
             Task::Classify => {
@@ -192,6 +198,9 @@ impl Yolo {
             Task::Segment => {
                 todo!();
             }
+            Task::Embed => {
+                panic!("Embedding models are loaded as an Embedder, not as Yolo");
+            }
         }
     }
 }
@@ -0,0 +1,67 @@
+// Motion gate for feeds: the detector only runs when something moves in the scene
+//
+// Frames are downscaled to grayscale and compared against a running average of the background,
+// motion is the fraction of pixels that changed more than `pixel_threshold`
+use super::config::MotionConfig;
+use image::{
+    imageops::{grayscale, resize, FilterType},
+    ImageBuffer, Rgb,
+};
+
+pub struct MotionDetector {
+    pub config: MotionConfig,
+    background: Option<Vec<f32>>,
+    // Frames left before the gate closes again
+    cooldown_left: u32,
+}
+
+impl MotionDetector {
+    pub fn new(config: MotionConfig) -> Self {
+        Self {
+            config,
+            background: None,
+            cooldown_left: 0,
+        }
+    }
+
+    fn downscale(&self, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Vec<f32> {
+        let width = self.config.downscale_width.max(1);
+        let height = ((img.height() as f32 / img.width().max(1) as f32) * width as f32).max(1.0) as u32;
+        let small = resize(&grayscale(img), width, height, FilterType::Triangle);
+        small.pixels().map(|p| p[0] as f32).collect()
+    }
+
+    // Fraction of pixels that changed, from 0 to 1. The background is updated afterwards
+    pub fn motion_score(&mut self, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> f32 {
+        let frame = self.downscale(img);
+        // First frame, or the resolution changed. Nothing to compare against yet
+        if self.background.as_ref().map(|bg| bg.len()) != Some(frame.len()) {
+            self.background = Some(frame);
+            return 1.0;
+        }
+        let background = self.background.as_mut().unwrap();
+
+        let mut changed = 0;
+        let alpha = self.config.learning_rate;
+        for (bg, px) in background.iter_mut().zip(&frame) {
+            if (px - *bg).abs() > self.config.pixel_threshold {
+                changed += 1;
+            }
+            *bg = (1.0 - alpha) * *bg + alpha * px;
+        }
+        changed as f32 / frame.len() as f32
+    }
+
+    // Motion opens the gate, it stays open for `cooldown_frames` after the motion stops
+    pub fn should_infer(&mut self, img: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> bool {
+        if self.motion_score(img) >= self.config.threshold {
+            self.cooldown_left = self.config.cooldown_frames;
+            return true;
+        }
+        if self.cooldown_left > 0 {
+            self.cooldown_left -= 1;
+            return true;
+        }
+        false
+    }
+}
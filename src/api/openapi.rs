@@ -0,0 +1,118 @@
+// OpenAPI document of the REST API, built from the handlers and types so it follows the code
+//
+// Served at /openapi.json, with Swagger UI at /docs
+use super::abstractions::{XYXYc, XYXY, AI};
+use super::auth::{self, KeyUsage};
+use super::config::{MotionConfig, SmoothingConfig};
+use super::health::{self, Health};
+use super::jobs::{
+    self, ImageResult, Job, JobKind, JobOptions, JobRequest, JobResults, JobState, JobStatus,
+    JobUpload,
+};
+use super::metrics;
+use super::rest;
+use super::stream::StreamStats;
+use super::tracking::TrackSummary;
+use super::v1::{
+    self, BatchItem, BatchUpload, ErrorBody, ErrorResponse, ImageSize, ImageUpload, ModelInfo,
+    ModelRef, Prediction, RenderFormat, VideoFormat, VideoUpload,
+};
+use super::video_file::FrameDetections;
+use super::zones::{Direction, Shape, Zone, ZoneConfig, ZoneCount};
+use axum::Router;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "BoquilaHUB Web API",
+        description = "Detections of the loaded model. When the server is deployed with an API keys file, every route except `/` needs `Authorization: Bearer <key>`"
+    ),
+    paths(
+        rest::root,
+        rest::upload,
+        rest::models,
+        rest::counts,
+        v1::models,
+        v1::predict,
+        v1::predict_batch,
+        v1::predict_video,
+        v1::render,
+        jobs::get_jobs,
+        jobs::post_job,
+        jobs::get_status,
+        jobs::delete_job,
+        jobs::results,
+        jobs::download,
+        auth::usage,
+        metrics::metrics,
+        health::healthz,
+        health::readyz,
+    ),
+    components(schemas(
+        XYXY,
+        XYXYc,
+        AI,
+        ModelInfo,
+        ModelRef,
+        ImageSize,
+        Prediction,
+        BatchItem,
+        ErrorBody,
+        ErrorResponse,
+        VideoFormat,
+        RenderFormat,
+        FrameDetections,
+        ImageUpload,
+        BatchUpload,
+        VideoUpload,
+        TrackSummary,
+        ZoneCount,
+        Direction,
+        ZoneConfig,
+        Zone,
+        Shape,
+        SmoothingConfig,
+        MotionConfig,
+        StreamStats,
+        Job,
+        JobKind,
+        JobState,
+        JobOptions,
+        JobStatus,
+        JobRequest,
+        JobUpload,
+        JobResults,
+        ImageResult,
+        KeyUsage,
+        Health,
+    )),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "models", description = "Models this server has"),
+        (name = "predictions", description = "Detections of images and videos"),
+        (name = "jobs", description = "Folders and videos processed in the background"),
+        (name = "feeds", description = "Zone counts of the video job that is running"),
+        (name = "admin", description = "Only for admin API keys"),
+        (name = "monitoring", description = "Prometheus metrics and health probes"),
+        (name = "legacy", description = "Routes used by older clients, prefer /v1"),
+    )
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("api_key", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+pub fn router() -> Router {
+    SwaggerUi::new("/docs")
+        .url("/openapi.json", ApiDoc::openapi())
+        .into()
+}
@@ -0,0 +1,121 @@
+// Client for another BoquilaHUB deployed with --deploy, used by the "BoquilaHUB Remoto" EP
+use super::abstractions::{XYXYc, AI};
+use super::health::Health;
+use reqwest::blocking::{multipart, Client};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::StatusCode;
+use std::time::{Duration, Instant};
+
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(3);
+const PREDICT_TIMEOUT: Duration = Duration::from_secs(30);
+// After a failed request the server is left alone for a while, so a dead server
+// doesn't add a timeout to every image
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+// Cheap to clone, the HTTP client shares its connection pool
+#[derive(Clone)]
+pub struct RemoteClient {
+    pub url: String,
+    client: Client,
+    down_since: Option<Instant>,
+}
+
+impl RemoteClient {
+    // `url` is the address of the server, e.g. http://192.168.1.20:8791
+    pub fn new(url: &str) -> Self {
+        Self::with_headers(url, HeaderMap::new())
+    }
+
+    // For servers deployed with API keys, the key is sent as a bearer token with every request.
+    // Keys that can't go in a header, e.g. with a line break, are an error
+    pub fn with_api_key(url: &str, api_key: Option<&str>) -> Result<Self, String> {
+        let mut headers = HeaderMap::new();
+        if let Some(key) = api_key {
+            let mut value = HeaderValue::from_str(&format!("Bearer {}", key.trim()))
+                .map_err(|_| "The API key has characters that can't be sent".to_string())?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+        Ok(Self::with_headers(url, headers))
+    }
+
+    fn with_headers(url: &str, headers: HeaderMap) -> Self {
+        let mut url = url.trim().trim_end_matches('/').to_string();
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            url = format!("http://{}", url);
+        }
+        Self {
+            url,
+            client: Client::builder()
+                .timeout(PREDICT_TIMEOUT)
+                .default_headers(headers)
+                .build()
+                .unwrap(),
+            down_since: None,
+        }
+    }
+
+    // GET on a probe, the status tells whether the server is ready, the body says why not
+    fn probe(&self, path: &str) -> Result<(StatusCode, Health), String> {
+        let response = self
+            .client
+            .get(format!("{}{}", self.url, path))
+            .timeout(HEALTH_TIMEOUT)
+            .send()
+            .map_err(|e| format!("{} is not reachable: {}", self.url, e))?;
+        let status = response.status();
+        if status == StatusCode::UNAUTHORIZED {
+            return Err(format!("{} needs a valid API key", self.url));
+        }
+        let body = response.text().map_err(|e| e.to_string())?;
+        serde_json::from_str(&body)
+            .map(|health| (status, health))
+            .map_err(|_| format!("{} is not a BoquilaHUB API, or one too old to have {}", self.url, path))
+    }
+
+    // Whether the server can take predictions right away
+    pub fn readiness(&self) -> Result<Health, String> {
+        match self.probe("/readyz")? {
+            (status, health) if status.is_success() => Ok(health),
+            (_, health) => Err(format!("{} is not ready: {}", self.url, health.status)),
+        }
+    }
+
+    pub fn list_models(&self) -> Result<Vec<AI>, String> {
+        self.client
+            .get(format!("{}/models", self.url))
+            .timeout(HEALTH_TIMEOUT)
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|e| e.to_string())
+            .and_then(|body| serde_json::from_str(&body).map_err(|e| e.to_string()))
+    }
+
+    pub fn is_down(&self) -> bool {
+        match self.down_since {
+            Some(since) => since.elapsed() < RETRY_AFTER,
+            None => false,
+        }
+    }
+
+    pub fn detect_bbox_from_buf(&self, buffer: Vec<u8>) -> Result<Vec<XYXYc>, String> {
+        let part = multipart::Part::bytes(buffer)
+            .file_name("image.jpg")
+            .mime_str("image/jpeg")
+            .unwrap();
+        self.client
+            .post(format!("{}/upload", self.url))
+            .multipart(multipart::Form::new().part("file", part))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.text())
+            .map_err(|e| e.to_string())
+            .and_then(|body| serde_json::from_str(&body).map_err(|e| e.to_string()))
+    }
+
+    // Failures are remembered, see `is_down`
+    pub fn record(&mut self, succeeded: bool) {
+        self.down_since = if succeeded { None } else { Some(Instant::now()) };
+    }
+}
@@ -0,0 +1,243 @@
+// Spreads a folder across several BoquilaHUB servers deployed with --deploy
+//
+// Every server gets `max_in_flight` workers, so that's the most requests it will see at once.
+// Failed images go back to the queue and are retried on a server that hasn't tried them yet
+use super::abstractions::{PredImg, XYXYc};
+use super::remote::RemoteClient;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+
+// A server is left out after this many failures in a row
+const MAX_CONSECUTIVE_FAILURES: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispatch {
+    // Image i goes to server i % n, unless it has to be retried
+    RoundRobin,
+    // Whichever server has a free worker takes the next image, so faster servers get more
+    LeastLoaded,
+}
+
+impl From<&str> for Dispatch {
+    fn from(s: &str) -> Self {
+        match s {
+            "round-robin" => Dispatch::RoundRobin,
+            _ => Dispatch::LeastLoaded,
+        }
+    }
+}
+
+struct Job {
+    index: usize,
+    server: Option<usize>,
+    tried: Vec<usize>,
+}
+
+// The jobs waiting for a worker, and how many are not finished yet
+struct Shared {
+    queue: VecDeque<Job>,
+    remaining: usize,
+}
+
+#[derive(Default)]
+struct ServerState {
+    done: AtomicUsize,
+    failed: AtomicUsize,
+    consecutive_failures: AtomicUsize,
+}
+
+impl ServerState {
+    fn is_alive(&self) -> bool {
+        self.consecutive_failures.load(Ordering::Relaxed) < MAX_CONSECUTIVE_FAILURES
+    }
+}
+
+// How the work was split, one per server
+#[derive(Clone, Debug)]
+pub struct ServerReport {
+    pub url: String,
+    pub done: usize,
+    pub failed: usize,
+}
+
+pub struct RemotePool {
+    pub clients: Vec<RemoteClient>,
+    pub dispatch: Dispatch,
+    pub max_in_flight: usize,
+}
+
+impl RemotePool {
+    pub fn new(urls: &[String], dispatch: Dispatch, max_in_flight: usize) -> Self {
+        Self {
+            clients: urls.iter().map(|url| RemoteClient::new(url)).collect(),
+            dispatch,
+            max_in_flight: max_in_flight.max(1),
+        }
+    }
+
+    pub fn with_api_key(
+        urls: &[String],
+        dispatch: Dispatch,
+        max_in_flight: usize,
+        api_key: Option<&str>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            clients: urls
+                .iter()
+                .map(|url| RemoteClient::with_api_key(url, api_key))
+                .collect::<Result<_, _>>()?,
+            dispatch,
+            max_in_flight: max_in_flight.max(1),
+        })
+    }
+
+    // Servers that aren't ready are removed from the pool, the reasons are returned
+    pub fn health_check(&mut self) -> Vec<String> {
+        let mut reasons = Vec::new();
+        self.clients.retain(|client| match client.readiness() {
+            Ok(_) => true,
+            Err(e) => {
+                reasons.push(e);
+                false
+            }
+        });
+        reasons
+    }
+
+    // Results come back in the same order as `paths`. Images that failed on every server
+    // are returned with `wasprocessed` set to false
+    pub fn predict<F>(&self, paths: Vec<PathBuf>, progress: F) -> (Vec<PredImg>, Vec<ServerReport>)
+    where
+        F: Fn(usize, usize) + Sync,
+    {
+        let n_servers = self.clients.len();
+        let total = paths.len();
+        let results: Vec<Mutex<Option<Vec<XYXYc>>>> = (0..total).map(|_| Mutex::new(None)).collect();
+        let states: Vec<ServerState> = (0..n_servers).map(|_| ServerState::default()).collect();
+
+        let shared = Mutex::new(Shared {
+            queue: (0..total)
+                .map(|index| Job {
+                    index,
+                    server: match self.dispatch {
+                        Dispatch::RoundRobin if n_servers > 0 => Some(index % n_servers),
+                        _ => None,
+                    },
+                    tried: Vec::new(),
+                })
+                .collect(),
+            remaining: total,
+        });
+        // Workers wait on it when there is nothing for them, every change to the queue wakes them
+        let changed = Condvar::new();
+
+        let finish = |shared: &mut Shared, job: &Job, result: Option<Vec<XYXYc>>| {
+            *results[job.index].lock().unwrap() = result;
+            shared.remaining -= 1;
+            progress(total - shared.remaining, total);
+        };
+        // A job is given up when every server that is still alive has tried it
+        let can_retry = |job: &Job| {
+            (0..n_servers).any(|s| states[s].is_alive() && !job.tried.contains(&s))
+        };
+
+        std::thread::scope(|scope| {
+            for (s, client) in self.clients.iter().enumerate() {
+                for _ in 0..self.max_in_flight {
+                    let (shared, changed, states, paths) = (&shared, &changed, &states, &paths);
+                    let (finish, can_retry) = (&finish, &can_retry);
+                    scope.spawn(move || loop {
+                        let job = {
+                            let mut guard = shared.lock().unwrap();
+                            loop {
+                                if guard.remaining == 0 || !states[s].is_alive() {
+                                    break None;
+                                }
+                                let position = guard.queue.iter().position(|job| {
+                                    job.server.map_or(true, |server| server == s)
+                                        && !job.tried.contains(&s)
+                                });
+                                if let Some(i) = position {
+                                    break guard.queue.remove(i);
+                                }
+                                // Retries may still show up for this server
+                                guard = changed.wait(guard).unwrap();
+                            }
+                        };
+                        let Some(mut job) = job else {
+                            break;
+                        };
+
+                        let result = std::fs::read(&paths[job.index])
+                            .map_err(|e| e.to_string())
+                            .and_then(|buffer| client.detect_bbox_from_buf(buffer));
+
+                        let mut guard = shared.lock().unwrap();
+                        match result {
+                            Ok(boxes) => {
+                                states[s].done.fetch_add(1, Ordering::SeqCst);
+                                if states[s].is_alive() {
+                                    states[s].consecutive_failures.store(0, Ordering::SeqCst);
+                                }
+                                finish(&mut *guard, &job, Some(boxes));
+                            }
+                            Err(e) => {
+                                eprintln!("{} failed on {}: {}", client.url, paths[job.index].display(), e);
+                                states[s].failed.fetch_add(1, Ordering::SeqCst);
+                                let failures =
+                                    states[s].consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+                                job.tried.push(s);
+                                job.server = None;
+                                if can_retry(&job) {
+                                    guard.queue.push_back(job);
+                                } else {
+                                    finish(&mut *guard, &job, None);
+                                }
+                                // This server is out, its jobs go to the others or are given up
+                                if failures == MAX_CONSECUTIVE_FAILURES {
+                                    let jobs: Vec<Job> = guard.queue.drain(..).collect();
+                                    for mut job in jobs {
+                                        if job.server == Some(s) {
+                                            job.server = None;
+                                        }
+                                        if can_retry(&job) {
+                                            guard.queue.push_back(job);
+                                        } else {
+                                            finish(&mut *guard, &job, None);
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        changed.notify_all();
+                    });
+                }
+            }
+        });
+
+        // Without any server every image is left unprocessed
+        let pred_imgs = paths
+            .into_iter()
+            .zip(results)
+            .map(|(path, result)| match result.into_inner().unwrap() {
+                Some(boxes) => PredImg::new(path, boxes, true),
+                None => PredImg::new_simple(path),
+            })
+            .collect();
+
+        let reports = self
+            .clients
+            .iter()
+            .zip(&states)
+            .map(|(client, state)| ServerReport {
+                url: client.url.clone(),
+                done: state.done.load(Ordering::SeqCst),
+                failed: state.failed.load(Ordering::SeqCst),
+            })
+            .collect();
+
+        (pred_imgs, reports)
+    }
+}
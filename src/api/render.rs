@@ -1,7 +1,10 @@
 use super::abstractions::{BoundingBoxTraitC, XYXYc};
+use super::zones::{Shape, ZoneConfig};
 use ab_glyph::FontRef;
 use image::{ImageBuffer, Rgb};
-use imageproc::drawing::{draw_filled_rect_mut, draw_hollow_rect_mut, draw_text_mut};
+use imageproc::drawing::{
+    draw_filled_rect_mut, draw_hollow_rect_mut, draw_line_segment_mut, draw_text_mut,
+};
 use imageproc::rect::Rect;
 
 const BBOX_COLORS: [Rgb<u8>; 90] = [
@@ -101,6 +104,7 @@ const FONT_SCALE: f32 = 36.4;
 const LABEL_PADDING: f32 = FONT_SCALE / 6.13;
 const CHAR_WIDTH: f32 = FONT_SCALE / 1.84;
 const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const ZONE_COLOR: Rgb<u8> = Rgb([255, 235, 59]);
 const FONT_BYTES: &[u8] = include_bytes!("../../assets//DejaVuSans.ttf");
 
 pub fn draw_bbox_from_file_path(
@@ -158,3 +162,41 @@ pub fn draw_bbox_from_imgbuf(img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, prediction
     }
 }
 
+// Polygons are drawn closed, the name of the zone goes next to its first point
+pub fn draw_zones(img: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, config: &ZoneConfig) {
+    let font: FontRef<'_> = FontRef::try_from_slice(FONT_BYTES).unwrap();
+
+    for zone in &config.zones {
+        let points: Vec<[f32; 2]> = match &zone.shape {
+            Shape::Polygon(points) => {
+                let mut points = points.clone();
+                if let Some(first) = points.first().copied() {
+                    points.push(first);
+                }
+                points
+            }
+            Shape::Line(line) => line.to_vec(),
+        };
+
+        for segment in points.windows(2) {
+            draw_line_segment_mut(
+                img,
+                (segment[0][0], segment[0][1]),
+                (segment[1][0], segment[1][1]),
+                ZONE_COLOR,
+            );
+        }
+
+        if let Some(first) = points.first() {
+            draw_text_mut(
+                img,
+                ZONE_COLOR,
+                first[0] as i32,
+                (first[1] - FONT_SCALE) as i32,
+                FONT_SCALE,
+                &font,
+                &zone.name,
+            );
+        }
+    }
+}
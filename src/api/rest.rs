@@ -1,87 +1,253 @@
-use super::abstractions::XYXYc;
+use super::abstractions::{XYXYc, AI};
+use super::auth::{self, require_key, KeyStore};
+use super::bq::get_bqs;
+use super::config::ApiConfig;
 use super::inference::*;
-use axum::{extract::Multipart, routing::get, routing::post, Router};
-use reqwest::blocking::Client;
-use std::os::windows::process::CommandExt;
-use std::process::Command;
-use std::str;
-
-async fn upload(mut multipart: Multipart) -> String {
-    let mut serialized: String = String::new();
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        // let name = field.name().unwrap().to_string();
-        let data = field.bytes().await.unwrap();
-        let imgbuf = image::load_from_memory(&data.to_vec()).unwrap().into_rgb8();
-        let test = detect_bbox_from_imgbuf(&imgbuf);
-        serialized = serde_json::to_string(&test).unwrap_or("Error".to_string());
-    }
-    return serialized;
+use super::health::{self, Health};
+use super::jobs::{set_jobs_root, start_jobs};
+use super::metrics;
+use super::openapi;
+use super::v1::{self, read_image, ApiError, ErrorResponse, ImageUpload};
+use super::zones::{get_live_counts, ZoneCount};
+use axum::{
+    extract::{multipart::MultipartRejection, DefaultBodyLimit, Multipart},
+    middleware,
+    routing::get,
+    routing::post,
+    Json, Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+// Photos from modern cameras go well over the 2 MB axum allows by default
+const MAX_UPLOAD_BYTES: usize = 64 * 1024 * 1024;
+
+// Kept for older clients, answers what /v1/models/{name}/predict puts in `detections`
+#[utoipa::path(
+    post,
+    path = "/upload",
+    tag = "legacy",
+    request_body(content = ImageUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Detections of the image", body = Vec<XYXYc>),
+        (status = 422, description = "The file is not an image", body = ErrorResponse),
+    )
+)]
+pub async fn upload(
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Json<Vec<XYXYc>>, ApiError> {
+    let img = read_image(&mut multipart?).await?;
+    let detections = tokio::task::spawn_blocking(move || try_detect_bbox_from_imgbuf(&img))
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .map_err(ApiError::internal)?;
+    Ok(Json(detections))
 }
 
-async fn root() -> &'static str {
-    "BoquilaHUB Web API!"
+// The models this server can run, used by remote clients
+#[utoipa::path(
+    get,
+    path = "/models",
+    tag = "legacy",
+    responses((status = 200, description = "Models in the models directory", body = Vec<AI>))
+)]
+pub async fn models() -> Json<Vec<AI>> {
+    Json(get_bqs())
 }
 
-pub async fn run_api() {
-    let app: Router = Router::new()
-        .route("/", get(root))
-        .route("/upload", post(upload));
+// Zone and line crossings of the video job that is running, or of the last one
+#[utoipa::path(
+    get,
+    path = "/counts",
+    tag = "feeds",
+    responses((status = 200, description = "Crossings per zone, label and direction", body = Vec<ZoneCount>))
+)]
+pub async fn counts() -> Json<Vec<ZoneCount>> {
+    Json(get_live_counts())
+}
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:8791").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "legacy",
+    responses((status = 200, description = "Always \"BoquilaHUB Web API!\", remote clients check it", body = String))
+)]
+pub async fn root() -> &'static str {
+    "BoquilaHUB Web API!"
 }
 
-pub fn detect_bbox_from_buf_remotely(url: String, buffer: Vec<u8>) -> Vec<XYXYc> {
-    let client = Client::new();
-    let response = client
-        .post(url)
-        .multipart(
-            reqwest::blocking::multipart::Form::new().part(
-                "file",
-                reqwest::blocking::multipart::Part::bytes(buffer)
-                    .mime_str("image/jpeg")
-                    .unwrap(),
-            ),
-        )
-        .send()
-        .expect("Failed to send request");
+// The unversioned routes are older than /v1 and stay for compatibility.
+// With API keys every route but / needs one, / stays open as the health check of remote clients
+pub fn router(keys: Option<Arc<KeyStore>>) -> Router {
+    let mut v1 = v1::router();
+    if let Some(keys) = &keys {
+        v1 = v1.merge(
+            Router::new()
+                .route("/admin/usage", get(auth::usage))
+                .with_state(keys.clone()),
+        );
+    }
 
-    let deserialized: Vec<XYXYc> = serde_json::from_str(&response.text().unwrap()).unwrap();
-    return deserialized;
+    let mut app = Router::new()
+        .route("/upload", post(upload))
+        .route("/models", get(models))
+        .route("/counts", get(counts))
+        .nest("/v1", v1);
+    if let Some(keys) = keys {
+        app = app.route_layer(middleware::from_fn_with_state(keys, require_key));
+    }
+    // The docs, metrics and probes stay open like `/`, Prometheus scrapes without a key
+    app.route("/", get(root))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .route("/metrics", get(metrics::metrics))
+        .merge(openapi::router())
+        .layer(middleware::from_fn(metrics::track_requests))
+        .layer(DefaultBodyLimit::max(MAX_UPLOAD_BYTES))
 }
 
-
-pub fn detect_bbox_remotely(url: String, file_path: &str) -> Vec<XYXYc> {
-    let buf = std::fs::read(file_path).unwrap_or(vec![]);
-    return detect_bbox_from_buf_remotely(url, buf);
+// A socket that is already bound, so errors show up before anything is printed
+pub struct ApiListener {
+    listener: std::net::TcpListener,
+    tls: Option<RustlsConfig>,
+    keys: Option<Arc<KeyStore>>,
+    jobs_root: Option<String>,
+    pub urls: Vec<String>,
 }
 
-pub const CREATE_NO_WINDOW: u32 = 0x08000000;
+pub async fn bind_api(config: &ApiConfig) -> Result<ApiListener, String> {
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(
+            RustlsConfig::from_pem_file(cert, key)
+                .await
+                .map_err(|e| format!("Failed to load the TLS certificate {} and key {}: {}", cert, key, e))?,
+        ),
+        (None, None) => None,
+        _ => return Err("The TLS certificate and key have to be set together".to_string()),
+    };
+    let keys = match &config.api_keys {
+        Some(path) => Some(Arc::new(KeyStore::load(path)?)),
+        None => None,
+    };
 
-fn get_ipv4_address() -> Option<String> {
-    let output = Command::new("ipconfig")
-        .creation_flags(CREATE_NO_WINDOW)
-        .output()
-        .expect("Failed to execute ipconfig");
+    let address = format!("{}:{}", config.host, config.port);
+    let listener = std::net::TcpListener::bind((config.host.as_str(), config.port)).map_err(|e| {
+        match e.kind() {
+            io::ErrorKind::AddrInUse => format!(
+                "Port {} is already in use, another BoquilaHUB may be running. Pick another port with --port",
+                config.port
+            ),
+            io::ErrorKind::PermissionDenied => format!(
+                "Not allowed to listen on {}, ports below 1024 usually need admin rights",
+                address
+            ),
+            _ => format!("Failed to listen on {}: {}", address, e),
+        }
+    })?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
 
-    let output_str = str::from_utf8(&output.stdout).expect("Failed to convert output to string");
+    Ok(ApiListener {
+        listener,
+        tls,
+        keys,
+        jobs_root: config.jobs_root.clone(),
+        urls: get_api_urls(config),
+    })
+}
 
-    for line in output_str.lines() {
-        if line.contains("IPv4 Address") {
-            // Extract the IP address (everything after the last ': ')
-            return line.split(": ").last().map(|ip| ip.trim().to_string());
+// Runs until the process ends
+pub async fn serve_api(api: ApiListener) -> Result<(), String> {
+    // Jobs left from the last run start again right away
+    set_jobs_root(api.jobs_root.as_deref());
+    start_jobs();
+    health::start_uptime();
+    // /readyz answers 503 until this is done
+    tokio::task::spawn_blocking(|| {
+        if get_model_info().is_some() {
+            if let Err(e) = warm_up() {
+                eprintln!("Failed to warm up the model: {}", e);
+            }
         }
-    }
+    });
+    let app: Router = router(api.keys);
+    let result = match api.tls {
+        Some(tls) => {
+            axum_server::from_tcp_rustls(api.listener, tls)
+                .serve(app.into_make_service())
+                .await
+        }
+        None => {
+            let listener = tokio::net::TcpListener::from_std(api.listener).map_err(|e| e.to_string())?;
+            axum::serve(listener, app).await
+        }
+    };
+    result.map_err(|e| e.to_string())
+}
 
-    None
+pub async fn run_api(config: &ApiConfig) -> Result<(), String> {
+    serve_api(bind_api(config).await?).await
 }
 
+// Every non-loopback address of this machine, IPv4 first
+pub fn get_ip_addresses() -> Vec<IpAddr> {
+    let mut ips: Vec<IpAddr> = if_addrs::get_if_addrs()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|iface| !iface.is_loopback())
+        .map(|iface| iface.ip())
+        .collect();
+    ips.sort_by_key(|ip| ip.is_ipv6());
+    ips.dedup();
+    ips
+}
+
+// What other devices in the network can use to reach the API. When listening on every
+// interface that's one URL per address, otherwise just the host that was set
+pub fn get_api_urls(config: &ApiConfig) -> Vec<String> {
+    let scheme = if config.uses_tls() { "https" } else { "http" };
+    let ips: Vec<IpAddr> = match config.host.parse::<IpAddr>() {
+        Ok(ip) if ip.is_unspecified() => {
+            let mut ips: Vec<IpAddr> = get_ip_addresses()
+                .into_iter()
+                .filter(|addr| ip.is_ipv6() || addr.is_ipv4())
+                .collect();
+            ips.push(IpAddr::V4(Ipv4Addr::LOCALHOST));
+            ips
+        }
+        Ok(ip) => vec![ip],
+        Err(_) => return vec![format!("{}://{}:{}", scheme, config.host, config.port)],
+    };
+    ips.into_iter()
+        .map(|ip| format!("{}://{}", scheme, SocketAddr::new(ip, config.port)))
+        .collect()
+}
+
+// The first IPv4 address, or localhost when the machine isn't connected to a network
 pub fn get_ip() -> String {
-    get_ipv4_address().unwrap()
+    get_ip_addresses()
+        .into_iter()
+        .find(|ip| ip.is_ipv4())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        .to_string()
 }
 
-pub async fn check_boquila_hub_api(url: &str) -> bool {
-    let response = reqwest::get(url).await.unwrap();
-    let body = response.text().await.unwrap();
-    body.trim() == "BoquilaHUB Web API!"
+// Whether `url` is a BoquilaHUB API that is alive, with the model it runs
+pub async fn check_boquila_hub_api(url: &str) -> Result<Health, String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(3))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let body = client
+        .get(format!("{}/healthz", url.trim_end_matches('/')))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("{} is not reachable: {}", url, e))?
+        .text()
+        .await
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&body).map_err(|_| format!("{} is not a BoquilaHUB API", url))
 }
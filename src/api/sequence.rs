@@ -0,0 +1,111 @@
+// Camera traps fire bursts of images for every trigger, an event groups the images of one burst
+use super::abstractions::{PredImg, XYXYc};
+use super::triage::TriageClass;
+use chrono::{NaiveDateTime, TimeDelta};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A group of images from the same camera folder, taken close in time
+/// # Fields
+/// - `images` are indices into the `PredImg` list the events were made from
+/// - `label` and `confidence` come from the most confident prediction of the event
+/// - `count` is the maximum number of `label` predictions seen in a single image
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub id: usize,
+    pub folder: PathBuf,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub images: Vec<usize>,
+    pub label: String,
+    pub confidence: f32,
+    pub count: usize,
+}
+
+fn best_bbox(list_bbox: &[XYXYc]) -> Option<&XYXYc> {
+    list_bbox.iter().max_by(|a, b| {
+        a.xyxy
+            .prob
+            .partial_cmp(&b.xyxy.prob)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    })
+}
+
+// The label of the most confident prediction, or "empty"
+pub fn main_label(list_bbox: &[XYXYc]) -> (String, f32) {
+    match best_bbox(list_bbox) {
+        Some(bbox) => (bbox.label.clone(), bbox.xyxy.prob),
+        None => (TriageClass::Empty.as_str().to_string(), 0.0),
+    }
+}
+
+// Images are split by folder, sorted by capture time, and a new event starts
+// every time the gap between two consecutive images is bigger than `max_gap`
+pub fn group_events(pred_imgs: &[PredImg], max_gap: TimeDelta) -> Vec<Event> {
+    let mut by_folder: BTreeMap<PathBuf, Vec<(NaiveDateTime, usize)>> = BTreeMap::new();
+    for (i, pred_img) in pred_imgs.iter().enumerate() {
+        if let Some(time) = pred_img.metadata().capture_time {
+            let folder = pred_img
+                .file_path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .to_path_buf();
+            by_folder.entry(folder).or_default().push((time, i));
+        }
+    }
+
+    let mut events: Vec<Event> = Vec::new();
+    for (folder, mut images) in by_folder {
+        images.sort();
+        let mut current: Vec<(NaiveDateTime, usize)> = Vec::new();
+        for image in images {
+            if let Some(last) = current.last() {
+                if image.0 - last.0 > max_gap {
+                    events.push(make_event(events.len(), &folder, &current, pred_imgs));
+                    current.clear();
+                }
+            }
+            current.push(image);
+        }
+        if !current.is_empty() {
+            events.push(make_event(events.len(), &folder, &current, pred_imgs));
+        }
+    }
+    events
+}
+
+fn make_event(
+    id: usize,
+    folder: &Path,
+    images: &[(NaiveDateTime, usize)],
+    pred_imgs: &[PredImg],
+) -> Event {
+    let all_bbox: Vec<XYXYc> = images
+        .iter()
+        .flat_map(|(_, i)| pred_imgs[*i].list_bbox.iter().cloned())
+        .collect();
+    let (label, confidence) = main_label(&all_bbox);
+
+    let count = images
+        .iter()
+        .map(|(_, i)| {
+            pred_imgs[*i]
+                .list_bbox
+                .iter()
+                .filter(|bbox| bbox.label == label)
+                .count()
+        })
+        .max()
+        .unwrap_or(0);
+
+    Event {
+        id,
+        folder: folder.to_path_buf(),
+        start: images.first().unwrap().0,
+        end: images.last().unwrap().0,
+        images: images.iter().map(|(_, i)| *i).collect(),
+        label,
+        confidence,
+        count,
+    }
+}
@@ -0,0 +1,134 @@
+// Temporal post-processing for videos and streams, so boxes don't jump and labels don't flicker
+//
+// Works on tracked detections: the label of every track is voted over the last `window`
+// inference frames, and boxes are interpolated on the frames between two inference frames.
+// Detections the tracker hasn't confirmed yet are only shown from their second frame,
+// so a one-frame false positive never is
+use super::abstractions::{BoundingBoxTrait, XYXYc, XYXY};
+use super::config::SmoothingConfig;
+use std::collections::{HashMap, VecDeque};
+
+// Overlap of an unconfirmed detection with one of the previous frame to be the same animal,
+// the tracker matches with the same threshold
+const MIN_IOU: f32 = 0.3;
+
+struct TrackHistory {
+    labels: VecDeque<(String, u16, f32)>,
+    last_frame: u64,
+}
+
+pub struct TemporalSmoother {
+    pub config: SmoothingConfig,
+    history: HashMap<u32, TrackHistory>,
+    // Detections without a track id of the last inference frame
+    unconfirmed: Vec<XYXYc>,
+    frame: u64,
+}
+
+impl TemporalSmoother {
+    pub fn new(config: SmoothingConfig) -> Self {
+        Self {
+            config,
+            history: HashMap::new(),
+            unconfirmed: Vec::new(),
+            frame: 0,
+        }
+    }
+
+    // Called once per inference frame with the output of the tracker.
+    // Detections without a track id are held until they show up in two inference frames
+    // in a row: the tracker only gives ids after a few frames, and waiting for them
+    // would hide every animal until then
+    pub fn smooth(&mut self, predictions: Vec<XYXYc>) -> Vec<XYXYc> {
+        self.frame += 1;
+        let window = self.config.window.max(1);
+
+        let mut output = Vec::new();
+        let mut unconfirmed = Vec::new();
+        for mut bbox in predictions {
+            let Some(id) = bbox.track_id else {
+                let seen_before = self
+                    .unconfirmed
+                    .iter()
+                    .any(|previous| previous.xyxy.iou(&bbox.xyxy) >= MIN_IOU);
+                if seen_before {
+                    output.push(bbox.clone());
+                }
+                unconfirmed.push(bbox);
+                continue;
+            };
+            let history = self.history.entry(id).or_insert(TrackHistory {
+                labels: VecDeque::new(),
+                last_frame: self.frame,
+            });
+            history.last_frame = self.frame;
+            history
+                .labels
+                .push_back((bbox.label.clone(), bbox.xyxy.class_id, bbox.xyxy.prob));
+            while history.labels.len() > window {
+                history.labels.pop_front();
+            }
+
+            let (label, class_id) = vote(&history.labels);
+            bbox.label = label;
+            bbox.xyxy.class_id = class_id;
+            output.push(bbox);
+        }
+
+        self.unconfirmed = unconfirmed;
+
+        // Forget tracks that were not seen for a whole window
+        let frame = self.frame;
+        self.history
+            .retain(|_, history| frame - history.last_frame <= window as u64);
+
+        output
+    }
+}
+
+// The label with the highest summed confidence in the window
+fn vote(labels: &VecDeque<(String, u16, f32)>) -> (String, u16) {
+    let mut scores: Vec<(String, u16, f32)> = Vec::new();
+    for (label, class_id, prob) in labels {
+        match scores.iter_mut().find(|(l, _, _)| l == label) {
+            Some((_, _, score)) => *score += prob,
+            None => scores.push((label.clone(), *class_id, *prob)),
+        }
+    }
+    scores
+        .into_iter()
+        .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(label, class_id, _)| (label, class_id))
+        .unwrap_or_default()
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// Boxes for a frame between two inference frames, `t` goes from 0 (prev) to 1 (next).
+// Tracks found in both frames are interpolated, the others are held from `prev`
+pub fn interpolate(prev: &[XYXYc], next: &[XYXYc], t: f32) -> Vec<XYXYc> {
+    prev.iter()
+        .map(|a| {
+            let matched = a
+                .track_id
+                .and_then(|id| next.iter().find(|b| b.track_id == Some(id)));
+            match matched {
+                Some(b) => {
+                    let mut bbox = a.clone();
+                    bbox.xyxy = XYXY {
+                        x1: lerp(a.xyxy.x1, b.xyxy.x1, t),
+                        y1: lerp(a.xyxy.y1, b.xyxy.y1, t),
+                        x2: lerp(a.xyxy.x2, b.xyxy.x2, t),
+                        y2: lerp(a.xyxy.y2, b.xyxy.y2, t),
+                        prob: lerp(a.xyxy.prob, b.xyxy.prob, t),
+                        class_id: a.xyxy.class_id,
+                    };
+                    bbox
+                }
+                None => a.clone(),
+            }
+        })
+        .collect()
+}
@@ -1,14 +1,23 @@
 use super::{
-    abstractions::XYXYc, inference::detect_bbox_from_imgbuf, render::draw_bbox_from_imgbuf,
-    rest::detect_bbox_from_buf_remotely, utils::image_buffer_to_jpg_buffer,
+    abstractions::XYXYc,
+    config::{MotionConfig, SmoothingConfig},
+    inference::detect_bbox_from_imgbuf,
+    motion::MotionDetector,
+    render::{draw_bbox_from_imgbuf, draw_zones},
+    smoothing::TemporalSmoother,
+    tracking::{TrackSummary, Tracker},
+    utils::image_buffer_to_jpg_buffer,
+    zones::{ZoneConfig, ZoneCounter},
 };
 use ffmpeg_next as ffmpeg;
 use image::{ImageBuffer, Rgb};
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::iter::Iterator;
 use std::time::{Duration, Instant};
 use std::{fs::File, io::Write};
 use chrono::Local;
+use utoipa::ToSchema;
 
 pub struct VideoStream {
     input_ctx: ffmpeg::format::context::Input,
@@ -16,10 +25,47 @@ pub struct VideoStream {
     index: usize,   
     decoded: ffmpeg::frame::Video,
     frames: i64,
+    // Tracking is optional for feeds, timestamps are seconds since the stream was opened
+    tracker: Option<Tracker>,
+    zones: Option<ZoneCounter>,
+    motion: Option<MotionDetector>,
+    smoother: Option<TemporalSmoother>,
+    stats: StreamStats,
+    started: Instant,
 }
 
 unsafe impl Sync for VideoStream {}
 
+/// What the stream or video has done since it was opened
+/// # Fields
+/// - `frames` are the frames the model could have run on, every n-th frame for videos
+/// - `frames_skipped` are the frames the motion gate kept away from the detector
+/// - `cpu_saved_secs` is an estimate: skipped frames times the average inference time
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default)]
+pub struct StreamStats {
+    pub frames: u64,
+    pub frames_inferred: u64,
+    pub frames_skipped: u64,
+    pub inference_secs: f64,
+    pub cpu_saved_secs: f64,
+}
+
+impl StreamStats {
+    pub fn avg_inference_secs(&self) -> f64 {
+        if self.frames_inferred == 0 {
+            return 0.0;
+        }
+        self.inference_secs / self.frames_inferred as f64
+    }
+
+    pub fn skipped_ratio(&self) -> f32 {
+        if self.frames == 0 {
+            return 0.0;
+        }
+        self.frames_skipped as f32 / self.frames as f32
+    }
+}
+
 impl Iterator for VideoStream {
     // The iterator yields image buffers
     type Item = ImageBuffer<Rgb<u8>, Vec<u8>>;
@@ -91,6 +137,12 @@ impl VideoStream {
             index,
             decoded,
             frames,
+            tracker: None,
+            zones: None,
+            motion: None,
+            smoother: None,
+            stats: StreamStats::default(),
+            started: Instant::now(),
         }
     }
 
@@ -104,10 +156,43 @@ impl VideoStream {
     {
         match self.next() {
             Some(mut img) => {
-                let predictions = prediction_fn(&img);
+                self.stats.frames += 1;
+                let infer = match self.motion.as_mut() {
+                    Some(motion) => motion.should_infer(&img),
+                    None => true,
+                };
+
+                let mut predictions = Vec::new();
+                if infer {
+                    let start = Instant::now();
+                    predictions = prediction_fn(&img);
+                    self.stats.inference_secs += start.elapsed().as_secs_f64();
+                    self.stats.frames_inferred += 1;
+                } else {
+                    self.stats.frames_skipped += 1;
+                    self.stats.cpu_saved_secs =
+                        self.stats.frames_skipped as f64 * self.stats.avg_inference_secs();
+                }
+
+                // Gated frames go through the tracker with no boxes, so tracks age while the
+                // gate is closed and a lost one can't take over the next unrelated detection
+                let timestamp = self.started.elapsed().as_secs_f64();
+                if let Some(tracker) = self.tracker.as_mut() {
+                    predictions = tracker.update(predictions, timestamp);
+                }
+                if let Some(smoother) = self.smoother.as_mut() {
+                    predictions = smoother.smooth(predictions);
+                }
+                if let Some(zones) = self.zones.as_mut() {
+                    zones.update(&predictions, timestamp);
+                }
+
+                if let Some(zones) = &self.zones {
+                    draw_zones(&mut img, &zones.config);
+                }
                 draw_bbox_from_imgbuf(&mut img, &predictions);
                 let jpg_buffer = image_buffer_to_jpg_buffer(img);
-                if log == true {
+                if log == true && infer {
                     let jpg_buffer_clone = jpg_buffer.clone();
                     std::thread::spawn(move || {
                         let now = Local::now();
@@ -122,22 +207,16 @@ impl VideoStream {
         }
     }
 
-    fn run(&mut self, log: bool) -> Result<(Vec<u8>, Vec<XYXYc>), Box<dyn Error>> {
+    // The annotated JPEG of the next frame and its boxes, an error once the stream ends.
+    // With the remote EP the frames go to the server, and to the local model when it fails
+    pub fn run(&mut self, log: bool) -> Result<(Vec<u8>, Vec<XYXYc>), Box<dyn Error>> {
         self.process_frame(|img| detect_bbox_from_imgbuf(img), log)
     }
 
-    fn run_remotely(&mut self, url: &str, log: bool) -> Result<(Vec<u8>, Vec<XYXYc>), Box<dyn Error>> {
-        self.process_frame(|img: &ImageBuffer<Rgb<u8>, Vec<u8>>| detect_bbox_from_buf_remotely(url.to_string(), img.to_vec()), log)
-    }
-
     pub fn run_exp(&mut self, log: bool) -> (Vec<u8>, Vec<XYXYc>) {
         self.run(log).unwrap()
     }
 
-    pub fn run_remotely_exp(&mut self, url: &str, log: bool) -> (Vec<u8>, Vec<XYXYc>) {
-        self.run_remotely(url, log).unwrap()
-    }
-
     pub fn ignore_frame(&mut self) {
         self.next();
     }
@@ -184,18 +263,58 @@ impl VideoStream {
         )
     }
 
-    pub fn measure_remote_inference(&mut self, iterations: u32, url: &str) -> u32 {
-        self.measure_method(
-            |s| {
-                s.run_remotely_exp(url,false);
-            },
-            iterations,
-        )
-    }
-
     pub fn get_n_frames(&self) -> i64 {
         self.frames
     }
+
+    pub fn enable_tracking(&mut self) {
+        self.tracker = Some(Tracker::default());
+        self.started = Instant::now();
+    }
+
+    // When enabled, the detector only runs on frames with motion, plus the cool-down after them
+    pub fn set_motion_gate(&mut self, config: &MotionConfig) {
+        self.motion = if config.enabled {
+            Some(MotionDetector::new(config.clone()))
+        } else {
+            None
+        };
+    }
+
+    pub fn get_stats(&self) -> StreamStats {
+        self.stats.clone()
+    }
+
+    // Smoothing needs track ids, so this turns tracking on as well
+    pub fn set_smoothing(&mut self, config: &SmoothingConfig) {
+        if !config.enabled {
+            self.smoother = None;
+            return;
+        }
+        if self.tracker.is_none() {
+            self.enable_tracking();
+        }
+        self.smoother = Some(TemporalSmoother::new(config.clone()));
+    }
+
+    // Zones need track ids, so this turns tracking on as well
+    pub fn enable_zones(&mut self, config: ZoneConfig) {
+        if self.tracker.is_none() {
+            self.enable_tracking();
+        }
+        self.zones = Some(ZoneCounter::new(config, true));
+    }
+
+    pub fn get_zone_counter(&self) -> Option<&ZoneCounter> {
+        self.zones.as_ref()
+    }
+
+    pub fn get_tracks(&self) -> Vec<TrackSummary> {
+        match &self.tracker {
+            Some(tracker) => tracker.summaries(),
+            None => Vec::new(),
+        }
+    }
 }
 
 /// Converts and saves a video frame as a PNG image
@@ -0,0 +1,308 @@
+// Multi-object tracking, so the same individual keeps the same id across frames
+//
+// ByteTrack-style association: confident detections are matched to the tracks first,
+// then the less confident ones get a chance with the tracks that are left.
+// Every track predicts where it will be with a constant velocity Kalman filter.
+use super::abstractions::{BoundingBoxTrait, XYXYc, XYXY};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// Constant velocity Kalman filter for one coordinate, state is position and velocity
+#[derive(Clone, Copy, Debug)]
+struct Kalman1D {
+    x: f32,
+    v: f32,
+    p: [[f32; 2]; 2],
+}
+
+const PROCESS_NOISE: f32 = 1.0;
+const MEASUREMENT_NOISE: f32 = 10.0;
+
+impl Kalman1D {
+    fn new(x: f32) -> Self {
+        Self {
+            x,
+            v: 0.0,
+            p: [[10.0, 0.0], [0.0, 1000.0]],
+        }
+    }
+
+    fn predict(&mut self) {
+        self.x += self.v;
+        let p = self.p;
+        self.p = [
+            [
+                p[0][0] + p[0][1] + p[1][0] + p[1][1] + PROCESS_NOISE,
+                p[0][1] + p[1][1],
+            ],
+            [p[1][0] + p[1][1], p[1][1] + PROCESS_NOISE * 0.01],
+        ];
+    }
+
+    fn update(&mut self, z: f32) {
+        let y = z - self.x;
+        let s = self.p[0][0] + MEASUREMENT_NOISE;
+        let k0 = self.p[0][0] / s;
+        let k1 = self.p[1][0] / s;
+        self.x += k0 * y;
+        self.v += k1 * y;
+        let p = self.p;
+        self.p = [
+            [(1.0 - k0) * p[0][0], (1.0 - k0) * p[0][1]],
+            [p[1][0] - k1 * p[0][0], p[1][1] - k1 * p[0][1]],
+        ];
+    }
+}
+
+/// A tracked individual
+/// # Fields
+/// - `filters` follow the center x, center y, width and height of the box
+/// - `hits` is the number of frames where the track was matched to a detection
+/// - `misses` is the number of frames since the last match
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub id: u32,
+    pub last: XYXYc,
+    pub hits: u32,
+    pub misses: u32,
+    pub first_seen: f64,
+    pub last_seen: f64,
+    pub max_prob: f32,
+    filters: [Kalman1D; 4],
+}
+
+impl Track {
+    fn new(id: u32, bbox: XYXYc, timestamp: f64) -> Self {
+        let (cx, cy, w, h) = center_size(&bbox.xyxy);
+        Self {
+            id,
+            max_prob: bbox.xyxy.prob,
+            last: bbox,
+            hits: 1,
+            misses: 0,
+            first_seen: timestamp,
+            last_seen: timestamp,
+            filters: [
+                Kalman1D::new(cx),
+                Kalman1D::new(cy),
+                Kalman1D::new(w),
+                Kalman1D::new(h),
+            ],
+        }
+    }
+
+    fn predict(&mut self) {
+        self.filters.iter_mut().for_each(|f| f.predict());
+    }
+
+    fn update(&mut self, bbox: XYXYc, timestamp: f64) {
+        let (cx, cy, w, h) = center_size(&bbox.xyxy);
+        self.filters[0].update(cx);
+        self.filters[1].update(cy);
+        self.filters[2].update(w);
+        self.filters[3].update(h);
+        self.max_prob = self.max_prob.max(bbox.xyxy.prob);
+        self.last = bbox;
+        self.hits += 1;
+        self.misses = 0;
+        self.last_seen = timestamp;
+    }
+
+    // Where the filter thinks the box is now
+    pub fn predicted(&self) -> XYXY {
+        let cx = self.filters[0].x;
+        let cy = self.filters[1].x;
+        let w = self.filters[2].x.max(1.0);
+        let h = self.filters[3].x.max(1.0);
+        XYXY::new(
+            cx - w / 2.0,
+            cy - h / 2.0,
+            cx + w / 2.0,
+            cy + h / 2.0,
+            self.last.xyxy.prob,
+            self.last.xyxy.class_id,
+        )
+    }
+
+    pub fn summary(&self) -> TrackSummary {
+        TrackSummary {
+            id: self.id,
+            label: self.last.label.clone(),
+            first_seen: self.first_seen,
+            last_seen: self.last_seen,
+            n_frames: self.hits,
+            max_prob: self.max_prob,
+        }
+    }
+}
+
+fn center_size(xyxy: &XYXY) -> (f32, f32, f32, f32) {
+    (
+        (xyxy.x1 + xyxy.x2) / 2.0,
+        (xyxy.y1 + xyxy.y2) / 2.0,
+        xyxy.x2 - xyxy.x1,
+        xyxy.y2 - xyxy.y1,
+    )
+}
+
+// One row per individual, timestamps are in seconds from the start of the video or stream
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct TrackSummary {
+    pub id: u32,
+    pub label: String,
+    pub first_seen: f64,
+    pub last_seen: f64,
+    pub n_frames: u32,
+    pub max_prob: f32,
+}
+
+/// # Fields
+/// - `high_threshold` splits detections between the first and the second association
+/// - `max_misses` is how many frames a track survives without detections
+/// - `min_hits` is how many matches a track needs before it gets reported
+pub struct Tracker {
+    pub tracks: Vec<Track>,
+    pub finished: Vec<TrackSummary>,
+    pub iou_threshold: f32,
+    pub high_threshold: f32,
+    pub max_misses: u32,
+    pub min_hits: u32,
+    next_id: u32,
+}
+
+impl Tracker {
+    pub fn new(iou_threshold: f32, high_threshold: f32, max_misses: u32, min_hits: u32) -> Self {
+        Self {
+            tracks: Vec::new(),
+            finished: Vec::new(),
+            iou_threshold,
+            high_threshold,
+            max_misses,
+            min_hits,
+            next_id: 1,
+        }
+    }
+
+    pub fn default() -> Self {
+        Tracker::new(0.3, 0.5, 30, 3)
+    }
+
+    // Returns the detections of this frame with their track id
+    pub fn update(&mut self, detections: Vec<XYXYc>, timestamp: f64) -> Vec<XYXYc> {
+        self.tracks.iter_mut().for_each(|t| t.predict());
+
+        let (high, low): (Vec<XYXYc>, Vec<XYXYc>) = detections
+            .into_iter()
+            .partition(|d| d.xyxy.prob >= self.high_threshold);
+
+        let mut unmatched_tracks: Vec<usize> = (0..self.tracks.len()).collect();
+        let mut output = Vec::new();
+
+        let unmatched_high = self.associate(high, &mut unmatched_tracks, timestamp, &mut output);
+        // Less confident detections can only keep existing tracks alive, never start new ones,
+        // the ones left are still returned, without an id
+        let unmatched_low = self.associate(low, &mut unmatched_tracks, timestamp, &mut output);
+        output.extend(unmatched_low);
+
+        for i in unmatched_tracks {
+            self.tracks[i].misses += 1;
+        }
+
+        for bbox in unmatched_high {
+            let track = Track::new(self.next_id, bbox.clone(), timestamp);
+            self.next_id += 1;
+            output.push(self.tag(&track, bbox));
+            self.tracks.push(track);
+        }
+
+        // Tracks that were lost for too long are done
+        let max_misses = self.max_misses;
+        let min_hits = self.min_hits;
+        let (lost, alive): (Vec<Track>, Vec<Track>) = std::mem::take(&mut self.tracks)
+            .into_iter()
+            .partition(|t| t.misses > max_misses);
+        self.tracks = alive;
+        self.finished.extend(
+            lost.iter()
+                .filter(|t| t.hits >= min_hits)
+                .map(|t| t.summary()),
+        );
+
+        output
+    }
+
+    // Greedy matching by IoU, best pairs first. A track keeps its label, so a detection of
+    // another class is never matched to it
+    fn associate(
+        &mut self,
+        detections: Vec<XYXYc>,
+        unmatched_tracks: &mut Vec<usize>,
+        timestamp: f64,
+        output: &mut Vec<XYXYc>,
+    ) -> Vec<XYXYc> {
+        let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+        for (d, detection) in detections.iter().enumerate() {
+            for &t in unmatched_tracks.iter() {
+                if self.tracks[t].last.label != detection.label {
+                    continue;
+                }
+                let iou = self.tracks[t].predicted().iou(&detection.xyxy);
+                if iou >= self.iou_threshold {
+                    pairs.push((iou, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut matched_detections = vec![false; detections.len()];
+        for (_, t, d) in pairs {
+            if matched_detections[d] || !unmatched_tracks.contains(&t) {
+                continue;
+            }
+            matched_detections[d] = true;
+            unmatched_tracks.retain(|&i| i != t);
+            self.tracks[t].update(detections[d].clone(), timestamp);
+            output.push(self.tag(&self.tracks[t], detections[d].clone()));
+        }
+
+        detections
+            .into_iter()
+            .zip(matched_detections)
+            .filter(|(_, matched)| !matched)
+            .map(|(detection, _)| detection)
+            .collect()
+    }
+
+    // Only confirmed tracks get an id, so one frame false positives don't count as individuals
+    fn tag(&self, track: &Track, mut bbox: XYXYc) -> XYXYc {
+        if track.hits >= self.min_hits {
+            bbox.track_id = Some(track.id);
+        }
+        bbox
+    }
+
+    // Every confirmed track, finished or not
+    pub fn summaries(&self) -> Vec<TrackSummary> {
+        let mut summaries = self.finished.clone();
+        summaries.extend(
+            self.tracks
+                .iter()
+                .filter(|t| t.hits >= self.min_hits)
+                .map(|t| t.summary()),
+        );
+        summaries.sort_by_key(|s| s.id);
+        summaries
+    }
+}
+
+// Number of individuals per label
+pub fn count_individuals(summaries: &[TrackSummary]) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for summary in summaries {
+        match counts.iter_mut().find(|(label, _)| *label == summary.label) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((summary.label.clone(), 1)),
+        }
+    }
+    counts
+}
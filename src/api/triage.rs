@@ -0,0 +1,108 @@
+// Triage is the first pass over camera-trap data: separating empty images from the rest
+use super::abstractions::{PredImg, XYXYc};
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum TriageClass {
+    Animal,
+    Person,
+    Vehicle,
+    Empty,
+}
+
+const PERSON_LABELS: [&'static str; 5] = ["person", "human", "people", "persona", "humano"];
+const VEHICLE_LABELS: [&'static str; 9] = [
+    "vehicle", "car", "truck", "bus", "motorcycle", "bicycle", "vehiculo", "vehículo", "auto",
+];
+
+impl TriageClass {
+    // Any label that is not a person or a vehicle is considered an animal
+    pub fn from_label(label: &str) -> Self {
+        let label = label.to_lowercase();
+        if PERSON_LABELS.contains(&label.as_str()) {
+            TriageClass::Person
+        } else if VEHICLE_LABELS.contains(&label.as_str()) {
+            TriageClass::Vehicle
+        } else {
+            TriageClass::Animal
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TriageClass::Animal => "animal",
+            TriageClass::Person => "person",
+            TriageClass::Vehicle => "vehicle",
+            TriageClass::Empty => "empty",
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == TriageClass::Empty
+    }
+}
+
+// The most confident prediction above the threshold decides the class of the image
+pub fn triage(list_bbox: &[XYXYc], threshold: f32) -> TriageClass {
+    list_bbox
+        .iter()
+        .filter(|bbox| bbox.xyxy.prob >= threshold)
+        .max_by(|a, b| {
+            a.xyxy
+                .prob
+                .partial_cmp(&b.xyxy.prob)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|bbox| TriageClass::from_label(&bbox.label))
+        .unwrap_or(TriageClass::Empty)
+}
+
+pub fn count_by_class(pred_imgs: &[PredImg], threshold: f32) -> Vec<(TriageClass, usize)> {
+    let classes = [
+        TriageClass::Animal,
+        TriageClass::Person,
+        TriageClass::Vehicle,
+        TriageClass::Empty,
+    ];
+    classes
+        .iter()
+        .map(|class| {
+            let n = pred_imgs
+                .iter()
+                .filter(|img| img.wasprocessed && triage(&img.list_bbox, threshold) == *class)
+                .count();
+            (*class, n)
+        })
+        .collect()
+}
+
+pub fn write_triage_report(
+    pred_imgs: &[PredImg],
+    threshold: f32,
+    output_path: &str,
+) -> io::Result<()> {
+    let mut wtr = Writer::from_path(output_path)?;
+    wtr.write_record(&["File Path", "Class", "Max Confidence", "n"])?;
+
+    for pred_img in pred_imgs.iter().filter(|img| img.wasprocessed) {
+        let above: Vec<&XYXYc> = pred_img
+            .list_bbox
+            .iter()
+            .filter(|bbox| bbox.xyxy.prob >= threshold)
+            .collect();
+        let max_prob = above.iter().map(|bbox| bbox.xyxy.prob).fold(0.0, f32::max);
+
+        wtr.write_record(&[
+            pred_img.file_path.to_string_lossy().into_owned(),
+            triage(&pred_img.list_bbox, threshold).as_str().to_string(),
+            max_prob.to_string(),
+            above.len().to_string(),
+        ])?;
+    }
+
+    wtr.flush()?;
+    Ok(())
+}
@@ -0,0 +1,669 @@
+// Version 1 of the REST API, nested under /v1 by `rest::router`
+//
+// Every error is answered with a JSON body like {"error": {"code": 404, "message": "..."}}
+use super::abstractions::{XYXYc, AI};
+use super::bq::get_bqs;
+use super::config::SmoothingConfig;
+use super::ensemble::get_pipelines;
+use super::inference::{get_model_info, try_detect_bbox_from_imgbuf};
+use super::jobs;
+use super::metrics;
+use super::render::draw_bbox_from_imgbuf;
+use super::video_file::{get_output_path, FrameDetections, VideofileProcessor};
+use axum::{
+    body::Body,
+    extract::{
+        multipart::{Field, MultipartRejection},
+        DefaultBodyLimit, Multipart, Path, Query,
+    },
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::imageops::FilterType;
+use image::{ImageBuffer, Rgb};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
+use utoipa::{IntoParams, ToSchema};
+
+// Files of a batch decoded and predicted at the same time
+pub const BATCH_CONCURRENCY: usize = 4;
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct ErrorBody {
+    pub code: u16,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorResponse {
+            error: ErrorBody {
+                code: self.status.as_u16(),
+                message: self.message,
+            },
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<ApiError> for ErrorBody {
+    fn from(error: ApiError) -> Self {
+        ErrorBody {
+            code: error.status.as_u16(),
+            message: error.message,
+        }
+    }
+}
+
+impl From<MultipartRejection> for ApiError {
+    fn from(rejection: MultipartRejection) -> Self {
+        ApiError::new(rejection.status(), rejection.body_text())
+    }
+}
+
+/// # Fields
+/// - `loaded` is true for the model this server answers predictions with
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ModelInfo {
+    #[serde(flatten)]
+    pub ai: AI,
+    pub loaded: bool,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone)]
+pub struct ModelRef {
+    pub name: String,
+    pub version: f32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ImageSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct Prediction {
+    pub model: ModelRef,
+    pub image: ImageSize,
+    pub inference_ms: f64,
+    pub detections: Vec<XYXYc>,
+}
+
+/// One file of a batch, either `prediction` or `error` is set
+/// # Fields
+/// - `name` is the file name of the part, or the field name when it has none
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchItem {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prediction: Option<Prediction>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorBody>,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoFormat {
+    // The annotated video
+    Mp4,
+    // Every frame with its boxes, in one array at the end
+    Json,
+    // One frame per line, sent while the video is processed
+    Ndjson,
+}
+
+/// Query of POST /v1/models/{name}/predict/video
+/// # Fields
+/// - `every_n_frames` runs the model on one frame out of n, the boxes in between are interpolated
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct VideoQuery {
+    pub every_n_frames: usize,
+    pub format: VideoFormat,
+}
+
+impl Default for VideoQuery {
+    fn default() -> Self {
+        Self {
+            every_n_frames: 1,
+            format: VideoFormat::Mp4,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    Jpeg,
+    Png,
+}
+
+/// Query of POST /v1/render
+/// # Fields
+/// - `quality` goes from 1 to 100, JPEG only
+/// - `max_size` is the longest side of the returned image, 0 keeps the size of the upload
+#[derive(Deserialize, IntoParams, Debug)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct RenderQuery {
+    pub format: RenderFormat,
+    pub quality: u8,
+    pub max_size: u32,
+}
+
+impl Default for RenderQuery {
+    fn default() -> Self {
+        Self {
+            format: RenderFormat::Jpeg,
+            quality: 90,
+            max_size: 0,
+        }
+    }
+}
+
+// The multipart forms, only used to describe them in the OpenAPI document
+#[derive(ToSchema)]
+pub struct ImageUpload {
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+#[derive(ToSchema)]
+pub struct BatchUpload {
+    // Any number of parts, each one named after its field or file name in the answer
+    #[schema(value_type = Vec<String>, format = Binary)]
+    pub files: Vec<Vec<u8>>,
+}
+
+#[derive(ToSchema)]
+pub struct VideoUpload {
+    #[schema(value_type = String, format = Binary)]
+    pub file: Vec<u8>,
+}
+
+static NEXT_VIDEO: AtomicU64 = AtomicU64::new(0);
+
+// Models in the 'models/' directory and ensemble pipelines
+pub fn list_models() -> Vec<ModelInfo> {
+    let loaded = get_model_info().map(|(name, _)| name);
+    let ais = get_bqs();
+    let pipelines: Vec<AI> = get_pipelines().iter().map(|p| p.to_ai()).collect();
+    ais.into_iter()
+        .chain(pipelines)
+        .map(|ai| ModelInfo {
+            loaded: loaded.as_deref() == Some(ai.name.as_str()),
+            ai,
+        })
+        .collect()
+}
+
+// The first file of the form, decoded
+pub async fn read_image(
+    multipart: &mut Multipart,
+) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>, ApiError> {
+    let field = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?
+        .ok_or(ApiError::bad_request("The form has no image, send it in the 'file' field"))?;
+    let data = field
+        .bytes()
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?;
+    decode_image(&data)
+}
+
+pub fn decode_image(data: &[u8]) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>, ApiError> {
+    let start = Instant::now();
+    let img = image::load_from_memory(data)
+        .map(|img| img.into_rgb8())
+        .map_err(|e| ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("Not an image: {}", e)))?;
+    metrics::observe_stage(metrics::DECODE, start);
+    Ok(img)
+}
+
+// 404 for a model that doesn't exist, 409 for one that exists but isn't the one loaded
+pub fn check_model(name: &str) -> Result<ModelRef, ApiError> {
+    let (loaded, version) = get_model_info()
+        .ok_or(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "No model loaded"))?;
+    if loaded == name {
+        return Ok(ModelRef {
+            name: loaded,
+            version,
+        });
+    }
+    if list_models().iter().any(|model| model.ai.name == name) {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("{} is not loaded, this server runs {}", name, loaded),
+        ));
+    }
+    Err(ApiError::not_found(format!("There is no model called {}", name)))
+}
+
+// Inference blocks, so it runs away from the async runtime
+pub async fn predict_imgbuf(
+    model: ModelRef,
+    img: ImageBuffer<Rgb<u8>, Vec<u8>>,
+) -> Result<Prediction, ApiError> {
+    let image = ImageSize {
+        width: img.width(),
+        height: img.height(),
+    };
+    let start = Instant::now();
+    let detections = tokio::task::spawn_blocking(move || try_detect_bbox_from_imgbuf(&img))
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .map_err(ApiError::internal)?;
+    Ok(Prediction {
+        model,
+        image,
+        inference_ms: start.elapsed().as_secs_f64() * 1000.0,
+        detections,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/models",
+    tag = "models",
+    responses((status = 200, description = "Models this server has, `loaded` marks the one in use", body = Vec<ModelInfo>))
+)]
+pub async fn models() -> Json<Vec<ModelInfo>> {
+    Json(list_models())
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/models/{name}/predict",
+    tag = "predictions",
+    params(("name" = String, Path, description = "Name of the loaded model")),
+    request_body(content = ImageUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Detections of the image", body = Prediction),
+        (status = 404, description = "There is no model with that name", body = ErrorResponse),
+        (status = 409, description = "The model exists but another one is loaded", body = ErrorResponse),
+        (status = 422, description = "The file is not an image", body = ErrorResponse),
+    )
+)]
+pub async fn predict(
+    Path(name): Path<String>,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Json<Prediction>, ApiError> {
+    let model = check_model(&name)?;
+    let img = read_image(&mut multipart?).await?;
+    Ok(Json(predict_imgbuf(model, img).await?))
+}
+
+// Every file of the form, results in the same order. A file that can't be read or predicted
+// gets an error of its own, the rest of the batch goes on
+#[utoipa::path(
+    post,
+    path = "/v1/models/{name}/predict/batch",
+    tag = "predictions",
+    params(("name" = String, Path, description = "Name of the loaded model")),
+    request_body(content = BatchUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "One item per file, in the order they were sent", body = Vec<BatchItem>),
+        (status = 400, description = "The form has no files", body = ErrorResponse),
+        (status = 404, description = "There is no model with that name", body = ErrorResponse),
+    )
+)]
+pub async fn predict_batch(
+    Path(name): Path<String>,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Json<Vec<BatchItem>>, ApiError> {
+    let model = check_model(&name)?;
+    let mut multipart = multipart?;
+
+    let mut files = Vec::new();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?
+    {
+        let name = field
+            .file_name()
+            .or(field.name())
+            .map(|name| name.to_string())
+            .unwrap_or(format!("file{}", files.len()));
+        let data = field
+            .bytes()
+            .await
+            .map_err(|e| ApiError::bad_request(e.body_text()))?;
+        files.push((name, data));
+    }
+    if files.is_empty() {
+        return Err(ApiError::bad_request("The form has no files"));
+    }
+
+    let semaphore = Arc::new(Semaphore::new(BATCH_CONCURRENCY));
+    let mut tasks = JoinSet::new();
+    for (index, (_, data)) in files.iter().enumerate() {
+        let (semaphore, model, data) = (semaphore.clone(), model.clone(), data.clone());
+        tasks.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.unwrap();
+            let result = async {
+                let img = tokio::task::spawn_blocking(move || decode_image(&data))
+                    .await
+                    .map_err(|e| ApiError::internal(e.to_string()))??;
+                predict_imgbuf(model, img).await
+            }
+            .await;
+            (index, result)
+        });
+    }
+
+    let mut results: Vec<Option<Result<Prediction, ApiError>>> = files.iter().map(|_| None).collect();
+    while let Some(joined) = tasks.join_next().await {
+        let (index, result) = joined.map_err(|e| ApiError::internal(e.to_string()))?;
+        results[index] = Some(result);
+    }
+
+    let items = files
+        .into_iter()
+        .zip(results)
+        .map(|((name, _), result)| match result.unwrap() {
+            Ok(prediction) => BatchItem {
+                name,
+                prediction: Some(prediction),
+                error: None,
+            },
+            Err(error) => BatchItem {
+                name,
+                prediction: None,
+                error: Some(error.into()),
+            },
+        })
+        .collect();
+    Ok(Json(items))
+}
+
+// The uploaded video and the annotated one are temporary files
+fn remove_video_files(input: &str) {
+    let _ = std::fs::remove_file(input);
+    let _ = std::fs::remove_file(get_output_path(input));
+}
+
+// Large uploads are written to disk in chunks as they arrive, instead of being held in memory
+pub async fn write_field(field: &mut Field<'_>, path: &str) -> Result<(), ApiError> {
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?;
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| ApiError::internal(e.to_string()))?;
+    }
+    file.flush().await.map_err(|e| ApiError::internal(e.to_string()))
+}
+
+// The decoder needs a file
+async fn save_video(multipart: &mut Multipart) -> Result<String, ApiError> {
+    let mut field = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError::bad_request(e.body_text()))?
+        .ok_or(ApiError::bad_request("The form has no video, send it in the 'file' field"))?;
+    let extension = field
+        .file_name()
+        .and_then(|name| std::path::Path::new(name).extension())
+        .map(|extension| extension.to_string_lossy().into_owned())
+        .unwrap_or("mp4".to_string());
+    let input = std::env::temp_dir().join(format!(
+        "boquilahub_{}_{}.{}",
+        std::process::id(),
+        NEXT_VIDEO.fetch_add(1, Ordering::SeqCst),
+        extension
+    ));
+    let input = input.to_string_lossy().into_owned();
+    write_field(&mut field, &input).await?;
+    Ok(input)
+}
+
+// Runs the whole video on the server instead of sending it frame by frame
+#[utoipa::path(
+    post,
+    path = "/v1/models/{name}/predict/video",
+    tag = "predictions",
+    params(("name" = String, Path, description = "Name of the loaded model"), VideoQuery),
+    request_body(content = VideoUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The annotated video, or the boxes of every frame", content(
+            (String = "video/mp4"),
+            (Vec<FrameDetections> = "application/json"),
+            (FrameDetections = "application/x-ndjson"),
+        )),
+        (status = 422, description = "The file is not a video", body = ErrorResponse),
+        (status = 500, description = "The video failed half way, the last line for ndjson", body = ErrorResponse),
+    )
+)]
+pub async fn predict_video(
+    Path(name): Path<String>,
+    Query(query): Query<VideoQuery>,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Response, ApiError> {
+    check_model(&name)?;
+    let input = save_video(&mut multipart?).await?;
+
+    let (ready_tx, ready_rx) = tokio::sync::oneshot::channel::<bool>();
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<FrameDetections>();
+    let n = query.every_n_frames;
+    let task = tokio::task::spawn_blocking({
+        let input = input.clone();
+        move || {
+            // The decoder panics on files it can't read
+            let processor = std::panic::catch_unwind(|| VideofileProcessor::new(&input));
+            let _ = ready_tx.send(processor.is_ok());
+            let Ok(mut processor) = processor else {
+                return Ok(());
+            };
+            processor.set_smoothing(&SmoothingConfig::default());
+            processor.set_frame_sink(move |frame| {
+                let _ = tx.send(frame);
+            });
+            // The answer has already started for ndjson, so a failure half way is an error
+            // at the end of the body instead of a cut off one
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+                processor.try_predict(n)?;
+                processor.finish();
+                Ok(())
+            }))
+            .unwrap_or_else(|panic| {
+                Err(panic
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or(panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or("The video crashed".to_string()))
+            })
+        }
+    });
+    // The error of the frame loop, or of the task itself
+    let finished = |outcome: Result<Result<(), String>, tokio::task::JoinError>| {
+        outcome
+            .map_err(|e| e.to_string())
+            .and_then(|outcome| outcome)
+            .map_err(|e| ApiError::internal(format!("The video failed half way: {}", e)))
+    };
+
+    if ready_rx.await != Ok(true) {
+        remove_video_files(&input);
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Could not read the video",
+        ));
+    }
+
+    match query.format {
+        VideoFormat::Mp4 => {
+            drop(rx);
+            let outcome = finished(task.await);
+            let output = get_output_path(&input);
+            let data = tokio::fs::read(&output).await;
+            remove_video_files(&input);
+            outcome?;
+            let data = data.map_err(|e| ApiError::internal(e.to_string()))?;
+            Ok(([(header::CONTENT_TYPE, "video/mp4")], data).into_response())
+        }
+        VideoFormat::Json => {
+            let frames: Vec<FrameDetections> = UnboundedReceiverStream::new(rx).collect().await;
+            let outcome = finished(task.await);
+            remove_video_files(&input);
+            outcome?;
+            Ok(Json(frames).into_response())
+        }
+        VideoFormat::Ndjson => {
+            // The frames end when the task drops the processor, then the last line is
+            // {"error": {...}} if it failed
+            let (error_tx, error_rx) = tokio::sync::mpsc::unbounded_channel::<ErrorResponse>();
+            tokio::spawn(async move {
+                let outcome = finished(task.await);
+                remove_video_files(&input);
+                if let Err(e) = outcome {
+                    let _ = error_tx.send(ErrorResponse { error: e.into() });
+                }
+            });
+            let frames = UnboundedReceiverStream::new(rx).map(|frame| serde_json::to_string(&frame));
+            let error = UnboundedReceiverStream::new(error_rx).map(|error| serde_json::to_string(&error));
+            let lines = frames
+                .chain(error)
+                .map(|line| Ok::<String, Infallible>(line.unwrap() + "\n"));
+            Ok((
+                [(header::CONTENT_TYPE, "application/x-ndjson")],
+                Body::from_stream(lines),
+            )
+                .into_response())
+        }
+    }
+}
+
+// Boxes are drawn at full size like in the desktop app, the image is scaled down afterwards
+pub fn render_image(
+    mut img: ImageBuffer<Rgb<u8>, Vec<u8>>,
+    detections: &Vec<XYXYc>,
+    query: &RenderQuery,
+) -> Result<Vec<u8>, String> {
+    draw_bbox_from_imgbuf(&mut img, detections);
+
+    let longest = img.width().max(img.height());
+    if query.max_size > 0 && longest > query.max_size {
+        let scale = query.max_size as f32 / longest as f32;
+        let width = ((img.width() as f32 * scale).round() as u32).max(1);
+        let height = ((img.height() as f32 * scale).round() as u32).max(1);
+        img = image::imageops::resize(&img, width, height, FilterType::Triangle);
+    }
+
+    let mut buffer = Vec::new();
+    match query.format {
+        RenderFormat::Jpeg => img.write_with_encoder(JpegEncoder::new_with_quality(
+            &mut buffer,
+            query.quality.clamp(1, 100),
+        )),
+        RenderFormat::Png => img.write_with_encoder(PngEncoder::new(&mut buffer)),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(buffer)
+}
+
+// Runs the loaded model and answers the image with the boxes drawn
+#[utoipa::path(
+    post,
+    path = "/v1/render",
+    tag = "predictions",
+    params(RenderQuery),
+    request_body(content = ImageUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The image with the boxes drawn", content(
+            (String = "image/jpeg"),
+            (String = "image/png"),
+        )),
+        (status = 503, description = "No model loaded", body = ErrorResponse),
+    )
+)]
+pub async fn render(
+    Query(query): Query<RenderQuery>,
+    multipart: Result<Multipart, MultipartRejection>,
+) -> Result<Response, ApiError> {
+    let (name, _) = get_model_info()
+        .ok_or(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "No model loaded"))?;
+    let model = check_model(&name)?;
+    let img = read_image(&mut multipart?).await?;
+    let prediction = predict_imgbuf(model, img.clone()).await?;
+
+    let content_type = match query.format {
+        RenderFormat::Jpeg => "image/jpeg",
+        RenderFormat::Png => "image/png",
+    };
+    let data = tokio::task::spawn_blocking(move || render_image(img, &prediction.detections, &query))
+        .await
+        .map_err(|e| ApiError::internal(e.to_string()))?
+        .map_err(ApiError::internal)?;
+    Ok(([(header::CONTENT_TYPE, content_type)], data).into_response())
+}
+
+async fn not_found() -> ApiError {
+    ApiError::not_found("No such route")
+}
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/models", get(models))
+        .route("/models/{name}/predict", post(predict))
+        .route("/models/{name}/predict/batch", post(predict_batch))
+        .route(
+            "/models/{name}/predict/video",
+            post(predict_video).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/render", post(render))
+        .nest("/jobs", jobs::router())
+        .fallback(not_found)
+}
@@ -1,12 +1,23 @@
 use super::abstractions::XYXYc;
-use super::inference::detect_bbox_from_imgbuf;
-use super::render::draw_bbox_from_imgbuf;
-use super::rest::detect_bbox_from_buf_remotely;
+use super::config::{MotionConfig, SmoothingConfig};
+use super::export::{write_csv_track_counts, write_csv_tracks, write_csv_zone_counts};
+use super::inference::{detect_bbox_from_imgbuf, try_detect_bbox_from_imgbuf};
+use super::metrics::count_gated_frame;
+use super::motion::MotionDetector;
+use super::render::{draw_bbox_from_imgbuf, draw_zones};
+use super::smoothing::{interpolate, TemporalSmoother};
+use super::stream::StreamStats;
+use super::tracking::{TrackSummary, Tracker};
+use super::zones::{ZoneConfig, ZoneCount, ZoneCounter};
 use super::utils::{image_buffer_to_jpg_buffer, image_buffer_to_ndarray, ndarray_to_image_buffer};
 use ndarray::{ArrayBase, Dim, OwnedRepr};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::error::Error;
+use std::time::Instant;
 use std::{iter::Iterator, path::Path};
+use utoipa::ToSchema;
 use video_rs::encode::Settings;
 use video_rs::{Decoder, DecoderBuilder, Encoder, Time, WriterBuilder};
 
@@ -56,18 +67,55 @@ use video_rs::{Decoder, DecoderBuilder, Encoder, Time, WriterBuilder};
 pub struct VideofileProcessor {
     decoder: Decoder,
     encoder: Encoder,
+    tracker: Tracker,
+    zones: Option<ZoneCounter>,
+    motion: Option<MotionDetector>,
+    smoother: Option<TemporalSmoother>,
+    stats: StreamStats,
+    frame_sink: Option<Box<dyn FnMut(FrameDetections) + Send>>,
+    frames_encoded: u64,
 }
 
+/// What a processed video gives besides the output file
+/// # Fields
+/// - `zones` are the crossings per zone, label and direction, empty without zones
+/// - `stats` counts the inference frames the motion gate skipped
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default)]
+pub struct VideoSummary {
+    pub tracks: Vec<TrackSummary>,
+    pub zones: Vec<ZoneCount>,
+    pub stats: StreamStats,
+}
+
+// The boxes drawn on one frame of the output video
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct FrameDetections {
+    pub frame: u64,
+    pub timestamp: f64,
+    pub detections: Vec<XYXYc>,
+}
+
+type Frame = image::ImageBuffer<image::Rgb<u8>, Vec<u8>>;
+
+// video.mp4 -> predict_video.mp4, in the same directory
 pub fn get_output_path(file_path: &str) -> String {
-    if let Some(pos) = file_path.rfind('\\') {
-        let (directory, file_name) = file_path.split_at(pos + 1);
-        let new_file_name = format!("predict_{}", file_name);
-        format!("{}{}", directory, new_file_name)
-    } else {
-        format!("predict_{}", file_path)
+    let path = Path::new(file_path);
+    match path.file_name() {
+        Some(file_name) => path
+            .with_file_name(format!("predict_{}", file_name.to_string_lossy()))
+            .to_string_lossy()
+            .into_owned(),
+        None => format!("predict_{}", file_path),
     }
 }
 
+// predict_video.mp4 -> predict_video_tracks.csv
+pub fn get_tracks_path(file_path: &str, suffix: &str) -> String {
+    let output_path = get_output_path(file_path);
+    let stem = Path::new(&output_path).with_extension("");
+    format!("{}_{}.csv", stem.to_string_lossy(), suffix)
+}
+
 impl VideofileProcessor {
     pub fn new(file_path: &str) -> Self {
         video_rs::init().unwrap();
@@ -90,13 +138,163 @@ impl VideofileProcessor {
         let settings = Settings::preset_h264_yuv420p(w as _, h as _, false);
         let encoder = Encoder::new(Path::new(&output_path), settings).unwrap();
 
-        Self { decoder, encoder }
+        Self {
+            decoder,
+            encoder,
+            tracker: Tracker::default(),
+            zones: ZoneConfig::for_video(file_path).map(|config| ZoneCounter::new(config, false)),
+            motion: None,
+            smoother: None,
+            stats: StreamStats::default(),
+            frame_sink: None,
+            frames_encoded: 0,
+        }
+    }
+
+    // Gets the boxes of every frame, in order, as the output video is written
+    pub fn set_frame_sink<S>(&mut self, sink: S)
+    where
+        S: FnMut(FrameDetections) + Send + 'static,
+    {
+        self.frame_sink = Some(Box::new(sink));
+    }
+
+    // Runs the loaded model every n frames over the whole video
+    pub fn predict(&mut self, n: usize) {
+        self.process_all(|img| detect_bbox_from_imgbuf(img), n, |_| true);
+    }
+
+    // Like `predict`, but stops at the first frame the model fails on, for the API
+    pub fn try_predict(&mut self, n: usize) -> Result<(), String> {
+        let error = RefCell::new(None);
+        self.process_all(
+            |img| {
+                try_detect_bbox_from_imgbuf(img).unwrap_or_else(|e| {
+                    *error.borrow_mut() = Some(e);
+                    Vec::new()
+                })
+            },
+            n,
+            |_| error.borrow().is_none(),
+        );
+        error.into_inner().map_or(Ok(()), Err)
+    }
+
+    // Writes the end of the output video, it can't be played until then
+    pub fn finish(&mut self) {
+        self.encoder.finish().unwrap();
+    }
+
+    pub fn set_smoothing(&mut self, config: &SmoothingConfig) {
+        self.smoother = if config.enabled {
+            Some(TemporalSmoother::new(config.clone()))
+        } else {
+            None
+        };
+    }
+
+    // Replaces the zones read from video_zones.json. Live counts are served at /counts
+    pub fn set_zones(&mut self, config: ZoneConfig, live: bool) {
+        self.zones = Some(ZoneCounter::new(config, live));
+    }
+
+    // When enabled, the model only runs on inference frames with motion, plus the cool-down after them
+    pub fn set_motion_gate(&mut self, config: &MotionConfig) {
+        self.motion = if config.enabled {
+            Some(MotionDetector::new(config.clone()))
+        } else {
+            None
+        };
+    }
+
+    pub fn get_stats(&self) -> StreamStats {
+        self.stats.clone()
+    }
+
+    pub fn get_zone_counts(&self) -> Vec<ZoneCount> {
+        self.zones.as_ref().map(|zones| zones.counts()).unwrap_or_default()
+    }
+
+    pub fn get_summary(&self) -> VideoSummary {
+        VideoSummary {
+            tracks: self.get_tracks(),
+            zones: self.get_zone_counts(),
+            stats: self.get_stats(),
+        }
     }
 
     pub fn get_n_frames(&self) -> u64 {
         self.decoder.frames().unwrap()
     }
 
+    pub fn get_tracks(&self) -> Vec<TrackSummary> {
+        self.tracker.summaries()
+    }
+
+    pub fn write_tracks(&self, file_path: &str) {
+        let tracks = self.get_tracks();
+        write_csv_tracks(&tracks, &get_tracks_path(file_path, "tracks")).unwrap();
+        write_csv_track_counts(&tracks, &get_tracks_path(file_path, "counts")).unwrap();
+        if let Some(zones) = &self.zones {
+            write_csv_zone_counts(&zones.binned(), &get_tracks_path(file_path, "zones")).unwrap();
+        }
+    }
+
+    // Tracking, then smoothing, then zone counting, for every inference frame
+    fn postprocess(&mut self, predictions: Vec<XYXYc>, timestamp: f64) -> Vec<XYXYc> {
+        let mut predictions = self.tracker.update(predictions, timestamp);
+        if let Some(smoother) = self.smoother.as_mut() {
+            predictions = smoother.smooth(predictions);
+        }
+        if let Some(zones) = self.zones.as_mut() {
+            zones.update(&predictions, timestamp);
+        }
+        predictions
+    }
+
+    // Runs the model unless the motion gate is closed, a closed gate gives no boxes
+    fn gated_predict<F>(&mut self, prediction_fn: &F, img: &Frame) -> Vec<XYXYc>
+    where
+        F: Fn(&Frame) -> Vec<XYXYc>,
+    {
+        self.stats.frames += 1;
+        let infer = match self.motion.as_mut() {
+            Some(motion) => motion.should_infer(img),
+            None => true,
+        };
+        if !infer {
+            self.stats.frames_skipped += 1;
+            self.stats.cpu_saved_secs =
+                self.stats.frames_skipped as f64 * self.stats.avg_inference_secs();
+            count_gated_frame(false, self.stats.avg_inference_secs());
+            return Vec::new();
+        }
+        let start = Instant::now();
+        let predictions = prediction_fn(img);
+        self.stats.inference_secs += start.elapsed().as_secs_f64();
+        self.stats.frames_inferred += 1;
+        count_gated_frame(true, 0.0);
+        predictions
+    }
+
+    // Draws the predictions and writes the frame to the output video
+    fn encode_frame(&mut self, img: &mut Frame, predictions: &Vec<XYXYc>, time: Time) {
+        if let Some(zones) = &self.zones {
+            draw_zones(img, &zones.config);
+        }
+        draw_bbox_from_imgbuf(img, predictions);
+        let final_frame = image_buffer_to_ndarray(img);
+        self.encoder.encode(&final_frame, time).unwrap(); // You may want to handle this unwrap as well
+        if let Some(sink) = self.frame_sink.as_mut() {
+            sink(FrameDetections {
+                frame: self.frames_encoded,
+                timestamp: time.as_secs_f64(),
+                detections: predictions.clone(),
+            });
+        }
+        self.frames_encoded += 1;
+    }
+
     // If the annotation is provided, it will just use that instead of computing it.
     fn process_frame<F>(
         &mut self,
@@ -104,20 +302,19 @@ impl VideofileProcessor {
         vec: Option<Vec<XYXYc>>,
     ) -> Result<(Vec<u8>, Vec<XYXYc>), Box<dyn Error>>
     where
-        F: Fn(&image::ImageBuffer<image::Rgb<u8>, Vec<u8>>) -> Vec<XYXYc>,
+        F: Fn(&Frame) -> Vec<XYXYc>,
     {
         match self.next() {
             Some((time, frame)) => {
                 let mut img = ndarray_to_image_buffer(&frame);
-                let predictions;
-                if vec.is_some() {
-                    predictions = vec.unwrap();
-                } else {
-                    predictions = prediction_fn(&img);
-                }
-                draw_bbox_from_imgbuf(&mut img, &predictions);
-                let final_frame = image_buffer_to_ndarray(&img);
-                self.encoder.encode(&final_frame, time).unwrap(); // You may want to handle this unwrap as well
+                let predictions = match vec {
+                    Some(vec) => vec,
+                    None => {
+                        let predictions = self.gated_predict(&prediction_fn, &img);
+                        self.postprocess(predictions, time.as_secs_f64())
+                    }
+                };
+                self.encode_frame(&mut img, &predictions, time);
                 let jpg_buffer = image_buffer_to_jpg_buffer(img);
                 Ok((jpg_buffer, predictions))
             }
@@ -125,32 +322,57 @@ impl VideofileProcessor {
         }
     }
 
-    fn run(&mut self, vec: Option<Vec<XYXYc>>) -> Result<(Vec<u8>, Vec<XYXYc>), Box<dyn Error>> {
-        self.process_frame(|img| detect_bbox_from_imgbuf(img), vec)
+    // Runs the model every n frames. The frames in between are held back until the next
+    // inference frame, so their boxes can be interpolated instead of repeated.
+    // `on_frame` gets the number of frames read so far, returning false stops the processing
+    fn process_all<F, P>(&mut self, prediction_fn: F, n: usize, mut on_frame: P) -> bool
+    where
+        F: Fn(&Frame) -> Vec<XYXYc>,
+        P: FnMut(u64) -> bool,
+    {
+        let n = n.max(1);
+        let mut pending: Vec<(Time, Frame)> = Vec::new();
+        let mut prev: Vec<XYXYc> = Vec::new();
+        let mut frame_count = 0;
+
+        while let Some((time, frame)) = self.next() {
+            if !on_frame(frame_count as u64 + 1) {
+                return false;
+            }
+            let mut img = ndarray_to_image_buffer(&frame);
+            if frame_count % n != 0 {
+                pending.push((time, img));
+                frame_count += 1;
+                continue;
+            }
+
+            let predictions = self.gated_predict(&prediction_fn, &img);
+            let predictions = self.postprocess(predictions, time.as_secs_f64());
+            let gap = pending.len() + 1;
+            for (i, (pending_time, mut pending_img)) in pending.drain(..).enumerate() {
+                let boxes = interpolate(&prev, &predictions, (i + 1) as f32 / gap as f32);
+                self.encode_frame(&mut pending_img, &boxes, pending_time);
+            }
+            self.encode_frame(&mut img, &predictions, time);
+            prev = predictions;
+            frame_count += 1;
+        }
+
+        // The last frames have no inference frame after them
+        for (pending_time, mut pending_img) in pending {
+            self.encode_frame(&mut pending_img, &prev, pending_time);
+        }
+        true
     }
 
-    fn run_remotely(
-        &mut self,
-        url: &str,
-        vec: Option<Vec<XYXYc>>,
-    ) -> Result<(Vec<u8>, Vec<XYXYc>), Box<dyn Error>> {
-        self.process_frame(
-            |img| detect_bbox_from_buf_remotely(url.to_string(), img.to_vec()),
-            vec,
-        )
+    // With the remote EP the frames go to the server, and to the local model when it fails
+    fn run(&mut self, vec: Option<Vec<XYXYc>>) -> Result<(Vec<u8>, Vec<XYXYc>), Box<dyn Error>> {
+        self.process_frame(|img| detect_bbox_from_imgbuf(img), vec)
     }
 
     pub fn run_exp(&mut self, vec: Option<Vec<XYXYc>>) -> (Vec<u8>, Vec<XYXYc>) {
         self.run(vec).unwrap()
     }
-
-    pub fn run_remotely_exp(
-        &mut self,
-        url: &str,
-        vec: Option<Vec<XYXYc>>,
-    ) -> (Vec<u8>, Vec<XYXYc>) {
-        self.run_remotely(url, vec).unwrap()
-    }
 }
 
 impl Iterator for VideofileProcessor {
@@ -164,42 +386,48 @@ impl Iterator for VideofileProcessor {
 }
 
 // Given a video file_path
-// We run inference for each frame then create a new videofile displayingthe predictions
-pub fn predict_videofile(file_path: &str, n: usize) {
+// We run inference every n frames then create a new videofile displaying the predictions
+pub fn predict_videofile(file_path: &str, n: usize, smoothing: &SmoothingConfig) {
     let mut frame_processor = VideofileProcessor::new(file_path);
-    let mut prev_bbox = None;
-    let mut frame_count = 0;
-
-    while let Ok((_jpeg, bbox)) = frame_processor.run(if frame_count % n == 0 {
-        None
-    } else {
-        prev_bbox.clone()
-    }) {
-        if frame_count % n == 0 {
-            prev_bbox = Some(bbox);
-        }
-        frame_count += 1;
-    }
+    frame_processor.set_smoothing(smoothing);
+    frame_processor.process_all(|img| detect_bbox_from_imgbuf(img), n, |_| true);
+    frame_processor.write_tracks(file_path);
 }
 
-// Given a video file_path
-// We run inference for each frame then create a new videofile displayingthe predictions
-pub fn predict_videofile_remotely(file_path: &str, url: &str, n: usize) {
+// Used by jobs: `on_frame` gets the frames read so far and the total, returning false cancels.
+// `frame_sink` gets the boxes of every frame, see `set_frame_sink`.
+// Zones given here replace video_zones.json, either are counted live for /counts.
+// Returns the tracks, zone counts and stats of the video, or None when it was cancelled
+pub fn predict_videofile_with_progress<S, P>(
+    file_path: &str,
+    n: usize,
+    smoothing: &SmoothingConfig,
+    motion: &MotionConfig,
+    zones: Option<ZoneConfig>,
+    frame_sink: S,
+    mut on_frame: P,
+) -> Option<VideoSummary>
+where
+    S: FnMut(FrameDetections) + Send + 'static,
+    P: FnMut(u64, u64) -> bool,
+{
     let mut frame_processor = VideofileProcessor::new(file_path);
-    let mut prev_bbox = None;
-    let mut frame_count = 0;
-
-    while let Ok((_jpeg, bbox)) = frame_processor.run_remotely(
-        url,
-        if frame_count % n == 0 {
-            None
-        } else {
-            prev_bbox.clone()
-        },
-    ) {
-        if frame_count % n == 0 {
-            prev_bbox = Some(bbox);
-        }
-        frame_count += 1;
+    frame_processor.set_smoothing(smoothing);
+    frame_processor.set_frame_sink(frame_sink);
+    frame_processor.set_motion_gate(motion);
+    if let Some(zones) = zones.or_else(|| ZoneConfig::for_video(file_path)) {
+        frame_processor.set_zones(zones, true);
+    }
+    let total = frame_processor.get_n_frames();
+    let completed = frame_processor.process_all(
+        |img| detect_bbox_from_imgbuf(img),
+        n,
+        |done| on_frame(done, total),
+    );
+    if !completed {
+        return None;
     }
+    frame_processor.finish();
+    frame_processor.write_tracks(file_path);
+    Some(frame_processor.get_summary())
 }
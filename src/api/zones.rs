@@ -0,0 +1,273 @@
+// Directional counting on top of tracked detections: polygons count enter/exit, lines count crossings
+//
+// Zones are configured per video or feed in a JSON file, coordinates are in pixels of the frame:
+// {
+//     "bin_seconds": 60,
+//     "zones": [
+//         { "name": "underpass", "polygon": [[100, 200], [400, 200], [400, 500], [100, 500]] },
+//         { "name": "ladder", "line": [[0, 300], [640, 300]] }
+//     ]
+// }
+use super::abstractions::XYXYc;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use utoipa::ToSchema;
+
+// Counts of the feed or video job that is running, or the last one, served by the REST API
+static LIVE_COUNTS: Lazy<Mutex<Vec<ZoneCount>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+pub fn get_live_counts() -> Vec<ZoneCount> {
+    LIVE_COUNTS.lock().unwrap().clone()
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    Polygon(Vec<[f32; 2]>),
+    Line([[f32; 2]; 2]),
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct Zone {
+    pub name: String,
+    #[serde(flatten)]
+    pub shape: Shape,
+}
+
+fn default_bin_seconds() -> f64 {
+    60.0
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug)]
+pub struct ZoneConfig {
+    #[serde(default = "default_bin_seconds")]
+    pub bin_seconds: f64,
+    pub zones: Vec<Zone>,
+}
+
+impl ZoneConfig {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // video.mp4 -> video_zones.json, next to the video
+    pub fn for_video(file_path: &str) -> Option<Self> {
+        let stem = Path::new(file_path).with_extension("");
+        let path = format!("{}_zones.json", stem.to_string_lossy());
+        ZoneConfig::load(&path).ok()
+    }
+}
+
+// Polygons give Enter and Exit. For lines, Forward means crossing from the left
+// to the right side of the line, looking from its first point to its second point
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Enter,
+    Exit,
+    Forward,
+    Backward,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Enter => "enter",
+            Direction::Exit => "exit",
+            Direction::Forward => "forward",
+            Direction::Backward => "backward",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrossingEvent {
+    pub timestamp: f64,
+    pub zone: String,
+    pub track_id: u32,
+    pub label: String,
+    pub direction: Direction,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct ZoneCount {
+    pub zone: String,
+    pub label: String,
+    pub direction: Direction,
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BinCount {
+    pub start: f64,
+    pub end: f64,
+    pub zone: String,
+    pub label: String,
+    pub direction: Direction,
+    pub count: usize,
+}
+
+// Positive when p is on the right of a -> b (image coordinates, y goes down)
+fn side(a: [f32; 2], b: [f32; 2], p: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+fn segments_cross(p1: [f32; 2], p2: [f32; 2], a: [f32; 2], b: [f32; 2]) -> bool {
+    side(a, b, p1) * side(a, b, p2) < 0.0 && side(p1, p2, a) * side(p1, p2, b) < 0.0
+}
+
+// Ray casting
+pub fn point_in_polygon(p: [f32; 2], polygon: &[[f32; 2]]) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn center(bbox: &XYXYc) -> [f32; 2] {
+    [
+        (bbox.xyxy.x1 + bbox.xyxy.x2) / 2.0,
+        (bbox.xyxy.y1 + bbox.xyxy.y2) / 2.0,
+    ]
+}
+
+/// Turns the movement of tracked detections into crossing events
+/// # Fields
+/// - `last_points` is the last center seen for every track id
+/// - `inside` holds the (track id, zone index) pairs that are currently inside a polygon
+/// - `live` publishes the totals for the REST API after every update
+pub struct ZoneCounter {
+    pub config: ZoneConfig,
+    pub events: Vec<CrossingEvent>,
+    pub live: bool,
+    last_points: HashMap<u32, [f32; 2]>,
+    inside: HashSet<(u32, usize)>,
+}
+
+impl ZoneCounter {
+    // A live counter starts from zero, the counts of the previous one are dropped
+    pub fn new(config: ZoneConfig, live: bool) -> Self {
+        if live {
+            LIVE_COUNTS.lock().unwrap().clear();
+        }
+        Self {
+            config,
+            events: Vec::new(),
+            live,
+            last_points: HashMap::new(),
+            inside: HashSet::new(),
+        }
+    }
+
+    // Detections without a track id are ignored, a single detection can't cross anything
+    pub fn update(&mut self, predictions: &[XYXYc], timestamp: f64) {
+        for bbox in predictions {
+            let Some(id) = bbox.track_id else {
+                continue;
+            };
+            let point = center(bbox);
+            let last = self.last_points.insert(id, point);
+
+            for (i, zone) in self.config.zones.iter().enumerate() {
+                let direction = match &zone.shape {
+                    Shape::Polygon(polygon) => {
+                        let now = point_in_polygon(point, polygon);
+                        let before = self.inside.contains(&(id, i));
+                        if now {
+                            self.inside.insert((id, i));
+                        } else {
+                            self.inside.remove(&(id, i));
+                        }
+                        // Tracks that start inside a zone didn't enter it
+                        match (last.is_some(), before, now) {
+                            (true, false, true) => Some(Direction::Enter),
+                            (true, true, false) => Some(Direction::Exit),
+                            _ => None,
+                        }
+                    }
+                    Shape::Line([a, b]) => match last {
+                        Some(last) if segments_cross(last, point, *a, *b) => {
+                            if side(*a, *b, point) > 0.0 {
+                                Some(Direction::Forward)
+                            } else {
+                                Some(Direction::Backward)
+                            }
+                        }
+                        _ => None,
+                    },
+                };
+
+                if let Some(direction) = direction {
+                    self.events.push(CrossingEvent {
+                        timestamp,
+                        zone: zone.name.clone(),
+                        track_id: id,
+                        label: bbox.label.clone(),
+                        direction,
+                    });
+                }
+            }
+        }
+
+        if self.live {
+            *LIVE_COUNTS.lock().unwrap() = self.counts();
+        }
+    }
+
+    // Totals per zone, label and direction
+    pub fn counts(&self) -> Vec<ZoneCount> {
+        let mut counts: BTreeMap<(String, String, Direction), usize> = BTreeMap::new();
+        for event in &self.events {
+            *counts
+                .entry((event.zone.clone(), event.label.clone(), event.direction))
+                .or_default() += 1;
+        }
+        counts
+            .into_iter()
+            .map(|((zone, label, direction), count)| ZoneCount {
+                zone,
+                label,
+                direction,
+                count,
+            })
+            .collect()
+    }
+
+    pub fn binned(&self) -> Vec<BinCount> {
+        bin_events(&self.events, self.config.bin_seconds)
+    }
+}
+
+// Events grouped in fixed time bins, empty bins are left out
+pub fn bin_events(events: &[CrossingEvent], bin_seconds: f64) -> Vec<BinCount> {
+    let mut bins: BTreeMap<(i64, String, String, Direction), usize> = BTreeMap::new();
+    for event in events {
+        let bin = (event.timestamp / bin_seconds).floor() as i64;
+        *bins
+            .entry((bin, event.zone.clone(), event.label.clone(), event.direction))
+            .or_default() += 1;
+    }
+    bins.into_iter()
+        .map(|((bin, zone, label, direction), count)| BinCount {
+            start: bin as f64 * bin_seconds,
+            end: (bin + 1) as f64 * bin_seconds,
+            zone,
+            label,
+            direction,
+            count,
+        })
+        .collect()
+}
@@ -5,26 +5,58 @@ use crate::api::abstractions::PredImgSugar;
 use crate::api::abstractions::XYXYc;
 use crate::api::abstractions::AI;
 use crate::api::bq::get_bqs;
-use crate::api::eps::LIST_EPS;
+use crate::api::config::{save_config, Config, CONFIG_PATH};
+use crate::api::embeddings::{EmbeddingIndex, Neighbour, INDEX_PATH};
+use crate::api::ensemble::{get_pipelines, Pipeline};
+use crate::api::eps::{available_eps, unprobed_eps, EP};
 use crate::api::inference::*;
+use crate::api::render::{draw_bbox_from_imgbuf, draw_zones};
+use crate::api::rest::{bind_api, serve_api};
+use crate::api::stream::VideoStream;
+use crate::api::video_file::predict_videofile_with_progress;
+use crate::api::zones::{get_live_counts, ZoneConfig, ZoneCount};
+use api::import::get_images_in_folder;
 use api::import::IMAGE_FORMATS;
 use api::import::VIDEO_FORMATS;
 use egui::{ColorImage, TextureHandle, TextureOptions};
-use image::open;
+use image::{open, DynamicImage};
 use rfd::FileDialog;
-use std::fs::{self};
 use std::path::PathBuf;
-use std::thread;
-use std::time::Duration;
+
+// What a video or feed analysis sends back to the GUI while it runs
+enum RunUpdate {
+    Progress(u64, u64),
+    Frame(Vec<u8>), // annotated JPEG of the feed
+    Counts(Vec<ZoneCount>),
+}
 
 pub struct MainApp {
+    config: Config,
+
     // Large types first (Vec, Option<PathBuf>, Option<String>)
     ais: Vec<AI>,
+    pipelines: Vec<Pipeline>,
+    eps: Vec<EP>,
     selected_files: Vec<PredImg>,
     video_file_path: Option<PathBuf>,
     feed_url: Option<String>,
     processing_receiver: Option<tokio::sync::mpsc::UnboundedReceiver<(usize, Vec<XYXYc>)>>,
     cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
+    embedding_index: Option<EmbeddingIndex>,
+    index_receiver: Option<tokio::sync::oneshot::Receiver<EmbeddingIndex>>,
+    similar: Vec<Neighbour>,
+    api_urls: Vec<String>,
+    api_error: Option<String>,
+    api_receiver: Option<tokio::sync::oneshot::Receiver<Result<Vec<String>, String>>>,
+    remote_url: String,
+    remote_api_key: String,
+    remote_models: Option<Result<Vec<AI>, String>>,
+    remote_receiver: Option<tokio::sync::oneshot::Receiver<Result<Vec<AI>, String>>>,
+    eps_receiver: Option<tokio::sync::oneshot::Receiver<Vec<EP>>>,
+    zones: Option<ZoneConfig>,
+    zone_counts: Vec<ZoneCount>,
+    run_receiver: Option<tokio::sync::mpsc::UnboundedReceiver<RunUpdate>>,
+    run_cancel_sender: Option<tokio::sync::oneshot::Sender<()>>,
 
     // Medium-sized types (TextureHandle options)
     screen_texture: Option<TextureHandle>,
@@ -56,19 +88,56 @@ pub struct MainApp {
 }
 
 impl MainApp {
-    pub fn new() -> Self {
-        set_model("models/boquilanet-gen.bq".to_owned(), LIST_EPS[1].clone());
-        Self {
-            ais: get_bqs(),
+    pub fn new(config: Config) -> Self {
+        // Ensembles are listed after the regular models
+        let mut ais = get_bqs();
+        let pipelines = get_pipelines();
+        let pipeline_ais: Vec<AI> = pipelines.iter().map(|p| p.to_ai()).collect();
+        ais.extend(pipeline_ais);
+
+        // boquilanet-gen is the default model, when it's available, otherwise the first detector.
+        // Embedding models can't be selected, they are used in the similarity panel
+        let ai_selected = ais
+            .iter()
+            .position(|ai| ai.name == "boquilanet-gen")
+            .or_else(|| ais.iter().position(|ai| ai.task != "embed"))
+            .unwrap_or(0);
+
+        // Probing the GPU EPs loads their libraries, so it's done off the GUI thread
+        let (eps_tx, eps_rx) = tokio::sync::oneshot::channel();
+        tokio::task::spawn_blocking(move || {
+            let _ = eps_tx.send(available_eps());
+        });
+
+        let app = Self {
+            config,
+            ais,
+            pipelines,
+            eps: unprobed_eps(),
             selected_files: Vec::new(),
             video_file_path: None,
             feed_url: None,
             processing_receiver: None,
             cancel_sender: None,
+            embedding_index: None,
+            index_receiver: None,
+            similar: Vec::new(),
+            api_urls: Vec::new(),
+            api_error: None,
+            api_receiver: None,
+            remote_url: String::new(),
+            remote_api_key: String::new(),
+            remote_models: None,
+            remote_receiver: None,
+            eps_receiver: Some(eps_rx),
+            zones: None,
+            zone_counts: Vec::new(),
+            run_receiver: None,
+            run_cancel_sender: None,
             screen_texture: None,
             video_frame: None,
             feed_frame: None,
-            ai_selected: 0,
+            ai_selected,
             ep_selected: 0,
             image_texture_n: 1, // this starts at 1
             step_frame: None,
@@ -82,15 +151,192 @@ impl MainApp {
             save_img_from_strema: false,
             error_ocurred: false,
             is_analysis_complete: false,
-        }
+        };
+
+        app.load_selected_model();
+        app
     }
 
     pub fn t(&self, key: Key) -> &'static str {
         translate(key, &self.lang)
     }
 
+    // The remote EP is the only one with a name to translate
+    fn ep_label(ep: &EP, lang: &Lang) -> &'static str {
+        if ep.local {
+            ep.name
+        } else {
+            translate(Key::remote_ep, lang)
+        }
+    }
+
+    pub fn load_selected_model(&self) {
+        let Some(ai) = self.ais.get(self.ai_selected).filter(|ai| ai.task != "embed") else {
+            return;
+        };
+        let ep = self.eps[self.ep_selected].clone();
+        // With the remote EP the model is still loaded locally (on the CPU), in case the server goes down
+        if ep.local {
+            clear_remote();
+        }
+        match self.pipelines.iter().find(|p| p.name == ai.name) {
+            Some(pipeline) => set_ensemble(pipeline.clone(), ep, &self.config.session),
+            None => set_model(ai.get_path(), ep, &self.config.session),
+        }
+    }
+
+    // The health check and the model list are blocking requests
+    fn connect_remote(&mut self) {
+        let url = self.remote_url.clone();
+        let api_key = Some(self.remote_api_key.trim().to_string()).filter(|key| !key.is_empty());
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.remote_receiver = Some(rx);
+        self.remote_models = None;
+        tokio::task::spawn_blocking(move || {
+            let _ = tx.send(set_remote_with_api_key(&url, api_key.as_deref()));
+        });
+    }
+
+    // The port is bound first, so a busy port is shown instead of failing in the background
+    fn deploy_api(&mut self) {
+        let config = self.config.api.clone();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.api_receiver = Some(rx);
+        self.api_error = None;
+        tokio::spawn(async move {
+            match bind_api(&config).await {
+                Ok(api) => {
+                    let _ = tx.send(Ok(api.urls.clone()));
+                    if let Err(e) = serve_api(api).await {
+                        eprintln!("The API stopped: {}", e);
+                    }
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                }
+            }
+        });
+    }
+
+    fn get_embedder_path(&self) -> Option<String> {
+        self.ais
+            .iter()
+            .find(|ai| ai.task == "embed")
+            .map(|ai| ai.get_path())
+    }
+
+    // Embeds every selected image in the background
+    fn build_index(&mut self, embedder_path: String) {
+        let paths: Vec<PathBuf> = self
+            .selected_files
+            .iter()
+            .map(|f| f.file_path.clone())
+            .collect();
+        let ep = self.eps[self.ep_selected].clone();
+        let config = self.config.session.clone();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.index_receiver = Some(rx);
+
+        tokio::task::spawn_blocking(move || {
+            set_embedder(embedder_path, ep, &config);
+            let mut index = EmbeddingIndex::new();
+            for path in &paths {
+                if let Err(e) = index.add_image(path) {
+                    eprintln!("Failed to embed {}: {}", path.display(), e);
+                }
+            }
+            if let Err(e) = index.save(INDEX_PATH) {
+                eprintln!("Failed to save the embedding index: {}", e);
+            }
+            let _ = tx.send(index);
+        });
+    }
+
+    // Channels of a video or feed run, an earlier run is cancelled
+    fn start_run(
+        &mut self,
+    ) -> (
+        tokio::sync::mpsc::UnboundedSender<RunUpdate>,
+        tokio::sync::oneshot::Receiver<()>,
+    ) {
+        if let Some(cancel_tx) = self.run_cancel_sender.take() {
+            let _ = cancel_tx.send(());
+        }
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel();
+        self.run_receiver = Some(rx);
+        self.run_cancel_sender = Some(cancel_tx);
+        self.zone_counts.clear();
+        self.current_frame = None;
+        self.total_frames = None;
+        self.feed_frame = None;
+        (tx, cancel_rx)
+    }
+
+    // Writes predict_<video> next to the video, crossings of the zones loaded in the GUI are counted
+    fn analyze_video(&mut self, path: PathBuf) {
+        let file_path = path.to_string_lossy().into_owned();
+        let zones = self.zones.clone();
+        let config = self.config.clone();
+        let (tx, mut cancel_rx) = self.start_run();
+        self.video_file_path = Some(path);
+
+        tokio::task::spawn_blocking(move || {
+            let summary = predict_videofile_with_progress(
+                &file_path,
+                1,
+                &config.smoothing,
+                &config.motion,
+                zones,
+                |_| {},
+                |done, total| {
+                    let _ = tx.send(RunUpdate::Progress(done, total));
+                    let _ = tx.send(RunUpdate::Counts(get_live_counts()));
+                    cancel_rx.try_recv().is_err()
+                },
+            );
+            if let Some(summary) = summary {
+                let _ = tx.send(RunUpdate::Counts(summary.zones));
+            }
+        });
+    }
+
+    // Runs until the feed ends or it's cancelled, with the zones loaded in the GUI
+    fn analyze_feed(&mut self, url: String) {
+        let zones = self.zones.clone();
+        let config = self.config.clone();
+        let (tx, mut cancel_rx) = self.start_run();
+
+        tokio::task::spawn_blocking(move || {
+            let mut stream = VideoStream::new(&url);
+            stream.set_motion_gate(&config.motion);
+            stream.set_smoothing(&config.smoothing);
+            if let Some(zones) = zones {
+                stream.enable_zones(zones);
+            }
+            while cancel_rx.try_recv().is_err() {
+                let Ok((jpg_buffer, _)) = stream.run(false) else {
+                    break;
+                };
+                let counts = stream
+                    .get_zone_counter()
+                    .map(|zones| zones.counts())
+                    .unwrap_or_default();
+                if tx.send(RunUpdate::Frame(jpg_buffer)).is_err()
+                    || tx.send(RunUpdate::Counts(counts)).is_err()
+                {
+                    break;
+                }
+            }
+        });
+    }
+
     pub fn paint(&mut self, ctx: &egui::Context, i: usize) {
-        self.screen_texture = Some(imgpred_to_texture(&self.selected_files[i], ctx))
+        self.screen_texture = Some(imgpred_to_texture(
+            &self.selected_files[i],
+            self.zones.as_ref(),
+            ctx,
+        ))
     }
 }
 
@@ -126,6 +372,49 @@ impl eframe::App for MainApp {
             });
         });
 
+        if let Some(rx) = &mut self.api_receiver {
+            match rx.try_recv() {
+                Ok(Ok(urls)) => {
+                    self.api_urls = urls;
+                    self.isapi_deployed = true;
+                    self.api_receiver = None;
+                    // Remembered for the next launch
+                    let _ = save_config(&self.config, CONFIG_PATH);
+                }
+                Ok(Err(e)) => {
+                    self.api_error = Some(e);
+                    self.api_receiver = None;
+                }
+                Err(tokio::sync::oneshot::error::TryRecvError::Empty) => ctx.request_repaint(),
+                Err(_) => self.api_receiver = None,
+            }
+        }
+
+        // The selected EP is kept, it's in both lists
+        if let Some(rx) = &mut self.eps_receiver {
+            match rx.try_recv() {
+                Ok(eps) => {
+                    let selected = self.eps[self.ep_selected].name;
+                    self.ep_selected = eps.iter().position(|ep| ep.name == selected).unwrap_or(0);
+                    self.eps = eps;
+                    self.eps_receiver = None;
+                }
+                Err(tokio::sync::oneshot::error::TryRecvError::Empty) => ctx.request_repaint(),
+                Err(_) => self.eps_receiver = None,
+            }
+        }
+
+        if let Some(rx) = &mut self.remote_receiver {
+            match rx.try_recv() {
+                Ok(result) => {
+                    self.remote_models = Some(result);
+                    self.remote_receiver = None;
+                }
+                Err(tokio::sync::oneshot::error::TryRecvError::Empty) => ctx.request_repaint(),
+                Err(_) => self.remote_receiver = None,
+            }
+        }
+
         egui::SidePanel::left("left_panel").show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.heading(format!("💻 {}", self.t(Key::setup)));
@@ -135,41 +424,147 @@ impl eframe::App for MainApp {
             ui.label(self.t(Key::select_ai));
 
             // AI Selection Widget
+            let previous_ai = self.ai_selected;
             egui::ComboBox::from_id_salt("AI")
-                .selected_text(&self.ais[self.ai_selected].name)
+                .selected_text(
+                    self.ais
+                        .get(self.ai_selected)
+                        .map(|ai| ai.name.as_str())
+                        .unwrap_or(""),
+                )
                 .show_ui(ui, |ui| {
                     for (i, ai) in self.ais.iter().enumerate() {
+                        // Embedding models are used in the similarity panel
+                        if ai.task == "embed" {
+                            continue;
+                        }
                         ui.selectable_value(&mut self.ai_selected, i, &ai.name)
                             .on_hover_text(&ai.classes.join(", "));
                     }
                 });
+            if self.ai_selected != previous_ai {
+                self.load_selected_model();
+            }
 
             ui.add_space(8.0);
 
-            // EP Selection Widget
-            let ep_alternatives = ["CPU", "CUDA", "Remote BoquilaHUB"];
+            // EP Selection Widget, only the EPs that work on this machine are listed
             ui.label(self.t(Key::select_ep));
-            egui::ComboBox::from_id_salt("EP").show_index(
-                ui,
-                &mut self.ep_selected,
-                ep_alternatives.len(),
-                |i| ep_alternatives[i],
-            );
+            let eps = &self.eps;
+            let lang = &self.lang;
+            if egui::ComboBox::from_id_salt("EP")
+                .show_index(ui, &mut self.ep_selected, eps.len(), |i| {
+                    Self::ep_label(&eps[i], lang)
+                })
+                .changed()
+            {
+                self.load_selected_model();
+            }
+            if self.eps_receiver.is_some() {
+                ui.spinner();
+            }
+            if let Some((name, skipped)) = get_active_ep() {
+                let label = ui.small(format!("▶ {}", name));
+                if !skipped.is_empty() {
+                    let reasons: Vec<String> = skipped
+                        .iter()
+                        .map(|skip| format!("{}: {}", skip.name, skip.reason))
+                        .collect();
+                    label.on_hover_text(reasons.join("\n"));
+                }
+            }
+
+            // Remote EP: every analysis is sent to another BoquilaHUB
+            if !self.eps[self.ep_selected].local {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.remote_url)
+                            .hint_text("http://192.168.1.20:8791")
+                            .desired_width(140.0),
+                    );
+                    if ui.button(self.t(Key::connect)).clicked() && self.remote_receiver.is_none() {
+                        self.connect_remote();
+                    }
+                });
+                // For servers deployed with API keys
+                let hint = self.t(Key::api_key);
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.remote_api_key)
+                        .hint_text(hint)
+                        .password(true)
+                        .desired_width(190.0),
+                );
+
+                if self.remote_receiver.is_some() {
+                    ui.spinner();
+                }
+                match &self.remote_models {
+                    Some(Ok(models)) => {
+                        ui.label(self.t(Key::server_models));
+                        for model in models {
+                            ui.small(&model.name);
+                        }
+                    }
+                    Some(Err(e)) => {
+                        ui.colored_label(egui::Color32::RED, self.t(Key::server_unreachable))
+                            .on_hover_text(e);
+                    }
+                    None => (),
+                }
+            }
 
             ui.add_space(8.0);
             ui.label("API ");
 
             if !self.isapi_deployed {
-                if ui.button(self.t(Key::deploy)).clicked() {
-                    tokio::spawn(async {
-                        thread::sleep(Duration::from_secs(2));
-                    });
-                    self.isapi_deployed = true;
+                ui.horizontal(|ui| {
+                    ui.label(self.t(Key::host));
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.config.api.host).desired_width(110.0),
+                    );
+                    ui.label(self.t(Key::port));
+                    ui.add(egui::DragValue::new(&mut self.config.api.port));
+                });
+                ui.horizontal(|ui| {
+                    if ui.button(self.t(Key::certificate)).clicked() {
+                        self.config.api.tls_cert = FileDialog::new()
+                            .add_filter("PEM", &["pem", "crt"])
+                            .pick_file()
+                            .map(|path| path.to_string_lossy().into_owned());
+                    }
+                    if ui.button(self.t(Key::private_key)).clicked() {
+                        self.config.api.tls_key = FileDialog::new()
+                            .add_filter("PEM", &["pem", "key"])
+                            .pick_file()
+                            .map(|path| path.to_string_lossy().into_owned());
+                    }
+                    if ui.button(self.t(Key::api_keys)).clicked() {
+                        self.config.api.api_keys = FileDialog::new()
+                            .add_filter("JSON", &["json"])
+                            .pick_file()
+                            .map(|path| path.to_string_lossy().into_owned());
+                    }
+                });
+                if self.config.api.uses_tls() {
+                    ui.small("HTTPS");
+                }
+                let deploying = self.api_receiver.is_some();
+                if ui
+                    .add_enabled(!deploying, egui::Button::new(self.t(Key::deploy)))
+                    .clicked()
+                {
+                    self.deploy_api();
+                }
+                if let Some(e) = &self.api_error {
+                    ui.colored_label(egui::Color32::RED, e);
                 }
             }
 
             if self.isapi_deployed {
                 ui.label(self.t(Key::deployed_api));
+                for url in &self.api_urls {
+                    ui.monospace(url);
+                }
             }
 
             ui.separator();
@@ -197,37 +592,17 @@ impl eframe::App for MainApp {
                         match FileDialog::new().pick_folder() {
                             Some(folder_path) => {
                                 // Read directory contents and filter for image files
-                                match fs::read_dir(&folder_path) {
-                                    Ok(entries) => {
-                                        let mut image_files = Vec::new();
-
-                                        for entry in entries {
-                                            if let Ok(entry) = entry {
-                                                let path = entry.path();
-
-                                                // Only process files (not subdirectories)
-                                                if path.is_file() {
-                                                    // Check if file extension matches IMAGE_FORMATS
-                                                    if let Some(extension) = path.extension() {
-                                                        if let Some(ext_str) = extension.to_str() {
-                                                            if IMAGE_FORMATS.iter().any(|&format| {
-                                                                ext_str.to_lowercase()
-                                                                    == format.to_lowercase()
-                                                            }) {
-                                                                image_files.push(path);
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-
+                                match get_images_in_folder(&folder_path) {
+                                    Ok(image_files) => {
                                         if !image_files.is_empty() {
                                             // Set the first image as the screen texture
                                             self.selected_files = image_files
                                                 .into_iter()
                                                 .map(|path| PredImg::new_simple(path))
                                                 .collect();
+                                            self.embedding_index = None;
+                                            self.similar.clear();
+                                            self.feed_frame = None;
 
                                             self.paint(ctx, 0);
 
@@ -257,6 +632,9 @@ impl eframe::App for MainApp {
                                     .into_iter()
                                     .map(|path| PredImg::new_simple(path))
                                     .collect();
+                                self.embedding_index = None;
+                                self.similar.clear();
+                                self.feed_frame = None;
                                 self.paint(ctx, 0)
                             }
                             _ => (), // no selection, do nothing
@@ -271,24 +649,122 @@ impl eframe::App for MainApp {
                     {
                         match FileDialog::new()
                             .add_filter("Video", &VIDEO_FORMATS)
-                            .pick_files()
+                            .pick_file()
                         {
-                            Some(paths) => {
-                                todo!()
-                            }
+                            Some(path) => self.analyze_video(path),
                             _ => (), // no selection, do nothing
                         }
                     }
 
-                    // Camera feed
+                    // Camera feed, the URL is asked for below
                     if ui
                         .add_sized([85.0, 40.0], egui::Button::new(self.t(Key::camera_feed)))
                         .clicked()
                     {
-                        // Camera feed logic here
+                        self.feed_url = match self.feed_url {
+                            Some(_) => None,
+                            None => Some(String::new()),
+                        };
+                    }
+                    ui.end_row();
+
+                    // Zones and lines for counting, drawn over the images
+                    if ui
+                        .add_sized([85.0, 40.0], egui::Button::new(self.t(Key::zones)))
+                        .clicked()
+                    {
+                        if let Some(path) = FileDialog::new().add_filter("JSON", &["json"]).pick_file() {
+                            match ZoneConfig::load(path.to_str().unwrap()) {
+                                Ok(zones) => {
+                                    self.zones = Some(zones);
+                                    if !self.selected_files.is_empty() {
+                                        self.paint(ctx, self.image_texture_n - 1);
+                                    }
+                                }
+                                Err(_) => self.error_ocurred = true,
+                            }
+                        }
                     }
                 });
 
+            let connect_label = self.t(Key::connect);
+            if let Some(url) = self.feed_url.as_mut() {
+                let mut connect = false;
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(url)
+                            .hint_text("rtsp://192.168.1.30:554/stream")
+                            .desired_width(190.0),
+                    );
+                    connect = ui.button(connect_label).clicked();
+                });
+                if connect && !url.trim().is_empty() {
+                    let url = url.trim().to_string();
+                    self.analyze_feed(url);
+                }
+            }
+
+            // Video and feed runs: progress and the crossings of each zone
+            if let Some(rx) = &mut self.run_receiver {
+                loop {
+                    match rx.try_recv() {
+                        Ok(RunUpdate::Progress(done, total)) => {
+                            self.current_frame = Some(done as usize);
+                            self.total_frames = Some(total as usize);
+                        }
+                        Ok(RunUpdate::Frame(jpg_buffer)) => {
+                            if let Ok(img) = image::load_from_memory(&jpg_buffer) {
+                                self.feed_frame = Some(ctx.load_texture(
+                                    "feed_frame",
+                                    load_image_from_buffer_ref(&img.to_rgba8()),
+                                    TextureOptions::default(),
+                                ));
+                            }
+                        }
+                        Ok(RunUpdate::Counts(counts)) => self.zone_counts = counts,
+                        Err(tokio::sync::mpsc::error::TryRecvError::Empty) => {
+                            ctx.request_repaint();
+                            break;
+                        }
+                        Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => {
+                            self.run_receiver = None;
+                            self.run_cancel_sender = None;
+                            break;
+                        }
+                    }
+                }
+            }
+            if self.run_receiver.is_some() {
+                ui.horizontal(|ui| {
+                    if let (Some(done), Some(total)) = (self.current_frame, self.total_frames) {
+                        ui.add(
+                            egui::ProgressBar::new(done as f32 / total.max(1) as f32)
+                                .show_percentage()
+                                .desired_width(140.0),
+                        );
+                    } else {
+                        ui.spinner();
+                    }
+                    if ui.button("Cancel").clicked() {
+                        if let Some(cancel_tx) = self.run_cancel_sender.take() {
+                            let _ = cancel_tx.send(());
+                        }
+                    }
+                });
+            }
+            if !self.zone_counts.is_empty() {
+                ui.label(self.t(Key::zones));
+                for count in &self.zone_counts {
+                    ui.small(format!(
+                        "{} · {} · {}: {}",
+                        count.zone,
+                        count.label,
+                        count.direction.as_str(),
+                        count.count
+                    ));
+                }
+            }
+
             if self.selected_files.len() > 0 {
                 ui.separator();
                 ui.vertical_centered(|ui| {
@@ -394,6 +870,73 @@ impl eframe::App for MainApp {
             }
         });
 
+        // Similarity search panel, only when there is an embedding model
+        if let Some(rx) = &mut self.index_receiver {
+            match rx.try_recv() {
+                Ok(index) => {
+                    self.embedding_index = Some(index);
+                    self.index_receiver = None;
+                }
+                Err(tokio::sync::oneshot::error::TryRecvError::Empty) => ctx.request_repaint(),
+                Err(_) => self.index_receiver = None,
+            }
+        }
+
+        if let Some(embedder_path) = self.get_embedder_path() {
+            if !self.selected_files.is_empty() {
+                egui::SidePanel::right("similar_panel").show(ctx, |ui| {
+                    ui.vertical_centered(|ui| {
+                        ui.heading(format!("🔍 {}", self.t(Key::similar_images)));
+                    });
+                    ui.separator();
+
+                    if self.index_receiver.is_some() {
+                        ui.spinner();
+                    } else if self.embedding_index.is_none() {
+                        if ui.button(self.t(Key::build_index)).clicked() {
+                            self.build_index(embedder_path);
+                        }
+                    } else if ui.button(self.t(Key::find_similar)).clicked() {
+                        let current = &self.selected_files[self.image_texture_n - 1].file_path;
+                        let index = self.embedding_index.as_ref().unwrap();
+                        match index.check_model() {
+                            Ok(()) => self.similar = index.similar_to(current, 8).unwrap_or_default(),
+                            // Built with another embedder, it's built again on the next click
+                            Err(e) => {
+                                eprintln!("{}", e);
+                                self.embedding_index = None;
+                                self.similar.clear();
+                                self.feed_frame = None;
+                            }
+                        }
+                    }
+
+                    ui.add_space(8.0);
+                    let mut clicked = None;
+                    for neighbour in &self.similar {
+                        let name = neighbour
+                            .file_path
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy();
+                        if ui
+                            .link(format!("{:.2}  {}", neighbour.similarity, name))
+                            .clicked()
+                        {
+                            clicked = self
+                                .selected_files
+                                .iter()
+                                .position(|f| f.file_path == neighbour.file_path);
+                        }
+                    }
+                    if let Some(i) = clicked {
+                        self.image_texture_n = i + 1;
+                        self.paint(ctx, i);
+                    }
+                });
+            }
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             // ui.image("https://i.pinimg.com/736x/a3/f5/d9/a3f5d95d519315eb158c867d7121dd3a.jpg");
             egui::ScrollArea::vertical().show(ui, |ui| {
@@ -408,6 +951,16 @@ impl eframe::App for MainApp {
                     }
                 }
 
+                // The feed being analyzed takes the place of the images
+                if let Some(texture) = &self.feed_frame {
+                    ui.add(
+                        egui::Image::new(texture)
+                            .max_height(800.0)
+                            .corner_radius(10.0),
+                    );
+                    return;
+                }
+
                 // If any textuure has been defined, we render it
                 match &self.screen_texture {
                     Some(texture) => {
@@ -432,11 +985,24 @@ fn load_image_from_buffer_ref(
     ColorImage::from_rgba_unmultiplied(size, pixels.as_slice())
 }
 
-fn imgpred_to_texture(predimg: &PredImg, ctx: &egui::Context) -> TextureHandle {
-    let image_data = if predimg.wasprocessed {
-        load_image_from_buffer_ref(&predimg.draw2())
-    } else {
-        load_image_from_buffer_ref(&open(predimg.file_path.clone()).unwrap().into_rgba8())
+fn imgpred_to_texture(
+    predimg: &PredImg,
+    zones: Option<&ZoneConfig>,
+    ctx: &egui::Context,
+) -> TextureHandle {
+    let image_data = match zones {
+        Some(zones) => {
+            let mut img = open(predimg.file_path.clone()).unwrap().into_rgb8();
+            draw_zones(&mut img, zones);
+            if predimg.wasprocessed {
+                draw_bbox_from_imgbuf(&mut img, &predimg.list_bbox);
+            }
+            load_image_from_buffer_ref(&DynamicImage::ImageRgb8(img).to_rgba8())
+        }
+        None if predimg.wasprocessed => load_image_from_buffer_ref(&predimg.draw2()),
+        None => {
+            load_image_from_buffer_ref(&open(predimg.file_path.clone()).unwrap().into_rgba8())
+        }
     };
 
     ctx.load_texture("current_img", image_data, TextureOptions::default())
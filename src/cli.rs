@@ -1,14 +1,61 @@
+use chrono::TimeDelta;
 use clap::{Arg, Command};
+use std::path::Path;
 
 use crate::api::{
-    abstractions::AI,
+    abstractions::{PredImg, AI},
     bq::get_bqs,
-    eps::LIST_EPS,
-    inference::set_model,
-    rest::{get_ip, run_api},
+    config::{load_config, Config, CONFIG_PATH},
+    ensemble::get_pipelines,
+    eps::best_ep,
+    export::{sort_by_triage, write_csv_events, FileMode},
+    import::get_images_in_folder,
+    embeddings::{EmbeddingIndex, INDEX_PATH},
+    inference::{
+        detect_bbox, set_embedder, set_ensemble, set_model,
+        set_remote_with_api_key,
+    },
+    models::CONFIDENCE_THRESHOLD,
+    remote_pool::{Dispatch, RemotePool},
+    rest::{bind_api, serve_api},
+    sequence::group_events,
+    triage::{count_by_class, write_triage_report},
 };
 
-pub async fn run_cli() {
+// Looks for a .bq model or an ensemble pipeline with that name in the 'models/' directory
+fn load_model_by_name(model_name: &str, config: &Config) {
+    let model_name = model_name.strip_suffix(".bq").unwrap_or(model_name);
+    let model_path = format!("models/{}.bq", model_name);
+    let ais: Vec<AI> = get_bqs();
+    let found = ais.iter().any(|ai| ai.get_path().contains(&model_path));
+    let pipeline = get_pipelines().into_iter().find(|p| p.name == model_name);
+
+    if found {
+        set_model(model_path, best_ep(), &config.session);
+    } else if let Some(pipeline) = pipeline {
+        set_ensemble(pipeline, best_ep(), &config.session);
+    } else {
+        panic!(
+            "Model path '{}' was not found in any of the registered AI paths.\n\
+    Make sure that the model '{}' (or '{}.bq') exists in the 'models/' directory",
+            model_path, model_name, model_name
+        );
+    }
+}
+
+fn load_embedder_by_name(model_name: &str, config: &Config) {
+    let model_name = model_name.strip_suffix(".bq").unwrap_or(model_name);
+    let model_path = format!("models/{}.bq", model_name);
+    if !Path::new(&model_path).exists() {
+        panic!(
+            "Embedding model '{}' was not found in the 'models/' directory",
+            model_name
+        );
+    }
+    set_embedder(model_path, best_ep(), &config.session);
+}
+
+pub async fn run_cli() -> Config {
     let matches = Command::new("BoquilaHUB")
         .version("1.0")
         .about("BoquilaHUB - GUI and CLI tool")
@@ -18,45 +65,454 @@ pub async fn run_cli() {
                 .help("Deploy mode")
                 .action(clap::ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("host")
+                .long("host")
+                .help("Address the API listens on, 0.0.0.0 for every network interface")
+                .value_name("HOST"),
+        )
+        .arg(
+            Arg::new("port")
+                .long("port")
+                .help("Port the API listens on")
+                .value_name("PORT")
+                .value_parser(clap::value_parser!(u16)),
+        )
+        .arg(
+            Arg::new("tls-cert")
+                .long("tls-cert")
+                .help("PEM certificate, serves the API over HTTPS")
+                .value_name("PATH")
+                .requires("tls-key"),
+        )
+        .arg(
+            Arg::new("tls-key")
+                .long("tls-key")
+                .help("PEM private key of the certificate")
+                .value_name("PATH")
+                .requires("tls-cert"),
+        )
+        .arg(
+            Arg::new("api-keys")
+                .long("api-keys")
+                .help("JSON file with the keys allowed to use the API, with their limits")
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::new("jobs-root")
+                .long("jobs-root")
+                .help("Folder that jobs of the API can read by path, without it only uploads are processed")
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::new("api-key")
+                .long("api-key")
+                .help("Key sent to the servers of --remote and --pool")
+                .value_name("KEY"),
+        )
         .arg(
             Arg::new("model")
                 .long("model")
                 .help("Model name to deploy")
-                .value_name("MODEL_NAME")
-                .requires("deploy"),
+                .value_name("MODEL_NAME"),
+        )
+        .arg(
+            Arg::new("triage")
+                .long("triage")
+                .help("Triage mode: classify every image in a folder as animal, person, vehicle or empty")
+                .value_name("FOLDER")
+                .requires("model"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .help("Minimum confidence for an image to be considered non empty, values below the model's own cutoff (0.45) act as 0.45")
+                .value_name("CONF")
+                .value_parser(clap::value_parser!(f32))
+                .default_value("0.5"),
+        )
+        .arg(
+            Arg::new("report")
+                .long("report")
+                .help("Where to write the triage report")
+                .value_name("PATH")
+                .default_value("export/triage.csv"),
+        )
+        .arg(
+            Arg::new("events")
+                .long("events")
+                .help("Also group the images into camera trap events and write them to this CSV")
+                .value_name("PATH")
+                .requires("triage"),
+        )
+        .arg(
+            Arg::new("event-gap")
+                .long("event-gap")
+                .help("Maximum seconds between two images of the same event")
+                .value_name("SECONDS")
+                .value_parser(clap::value_parser!(i64))
+                .default_value("60"),
+        )
+        .arg(
+            Arg::new("sort-into")
+                .long("sort-into")
+                .help("Sort the images into empty/ and non_empty/ folders inside this directory")
+                .value_name("DIR")
+                .requires("triage"),
+        )
+        .arg(
+            Arg::new("file-mode")
+                .long("file-mode")
+                .help("How the images are sorted")
+                .value_parser(["copy", "move", "link"])
+                .default_value("copy"),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help("Path to the config file")
+                .value_name("PATH")
+                .default_value(CONFIG_PATH),
+        )
+        .arg(
+            Arg::new("intra-threads")
+                .long("intra-threads")
+                .help("Threads used within each operator, 0 lets ONNX Runtime decide")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("inter-threads")
+                .long("inter-threads")
+                .help("Threads used across operators in parallel execution mode")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("parallel")
+                .long("parallel")
+                .help("Use the parallel execution mode")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("opt-level")
+                .long("opt-level")
+                .help("Graph optimization level, from 0 (disabled) to 3 (all)")
+                .value_name("LEVEL")
+                .value_parser(clap::value_parser!(u8).range(0..=3)),
+        )
+        .arg(
+            Arg::new("no-memory-pattern")
+                .long("no-memory-pattern")
+                .help("Disable the memory pattern optimization")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-model-cache")
+                .long("no-model-cache")
+                .help("Don't save or load optimized models from the cache")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("embed")
+                .long("embed")
+                .help("Compute the embeddings of every image in a folder and add them to the index")
+                .value_name("FOLDER")
+                .requires("embedder"),
+        )
+        .arg(
+            Arg::new("similar")
+                .long("similar")
+                .help("Print the images of the index that are most similar to this one")
+                .value_name("IMAGE")
+                .requires("embedder"),
+        )
+        .arg(
+            Arg::new("embedder")
+                .long("embedder")
+                .help("Embedding model used for similarity search")
+                .value_name("MODEL_NAME"),
+        )
+        .arg(
+            Arg::new("crops")
+                .long("crops")
+                .help("One embedding per detection instead of per image, detections come from --model")
+                .action(clap::ArgAction::SetTrue)
+                .requires("model"),
+        )
+        .arg(
+            Arg::new("index")
+                .long("index")
+                .help("Path of the embedding index")
+                .value_name("PATH")
+                .default_value(INDEX_PATH),
+        )
+        .arg(
+            Arg::new("k")
+                .long("k")
+                .help("Number of similar images to return")
+                .value_name("K")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("remote")
+                .long("remote")
+                .help("Send the images to another BoquilaHUB (e.g. http://192.168.1.20:8791), the local model is used when it can't be reached")
+                .value_name("URL"),
+        )
+        .arg(
+            Arg::new("pool")
+                .long("pool")
+                .help("Split the triage between several BoquilaHUB servers, comma separated")
+                .value_name("URLS")
+                .value_delimiter(',')
+                .num_args(1..)
+                .requires("triage"),
+        )
+        .arg(
+            Arg::new("dispatch")
+                .long("dispatch")
+                .help("How images are handed to the servers of the pool")
+                .value_parser(["round-robin", "least-loaded"])
+                .default_value("least-loaded"),
+        )
+        .arg(
+            Arg::new("in-flight")
+                .long("in-flight")
+                .help("Maximum number of images sent at once to each server of the pool")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("4"),
         )
         .get_matches();
 
+    // CLI flags override the config file
+    let mut config = load_config(matches.get_one::<String>("config").unwrap());
+    if let Some(n) = matches.get_one::<usize>("intra-threads") {
+        config.session.intra_threads = *n;
+    }
+    if let Some(n) = matches.get_one::<usize>("inter-threads") {
+        config.session.inter_threads = *n;
+    }
+    if matches.get_flag("parallel") {
+        config.session.parallel_execution = true;
+    }
+    if let Some(level) = matches.get_one::<u8>("opt-level") {
+        config.session.optimization_level = *level;
+    }
+    if matches.get_flag("no-memory-pattern") {
+        config.session.memory_pattern = false;
+    }
+    if matches.get_flag("no-model-cache") {
+        config.session.cache_optimized = false;
+    }
+    if let Some(host) = matches.get_one::<String>("host") {
+        config.api.host = host.clone();
+    }
+    if let Some(port) = matches.get_one::<u16>("port") {
+        config.api.port = *port;
+    }
+    if let Some(cert) = matches.get_one::<String>("tls-cert") {
+        config.api.tls_cert = Some(cert.clone());
+    }
+    if let Some(key) = matches.get_one::<String>("tls-key") {
+        config.api.tls_key = Some(key.clone());
+    }
+    if let Some(path) = matches.get_one::<String>("api-keys") {
+        config.api.api_keys = Some(path.clone());
+    }
+    if let Some(path) = matches.get_one::<String>("jobs-root") {
+        config.api.jobs_root = Some(path.clone());
+    }
+
+    if let Some(url) = matches.get_one::<String>("remote") {
+        let url = url.clone();
+        let api_key = matches.get_one::<String>("api-key").cloned();
+        let connect = move || set_remote_with_api_key(&url, api_key.as_deref());
+        match tokio::task::spawn_blocking(connect).await.unwrap() {
+            Ok(models) => println!("Connected to the remote BoquilaHUB, {} models", models.len()),
+            Err(e) => eprintln!("{}, using the local model", e),
+        }
+    }
+
     // Check if CLI arguments are provided
-    if matches.get_flag("deploy") {
+    if let Some(folder) = matches.get_one::<String>("embed") {
+        load_embedder_by_name(matches.get_one::<String>("embedder").unwrap(), &config);
+        let crops = matches.get_flag("crops");
+        if crops {
+            load_model_by_name(matches.get_one::<String>("model").unwrap(), &config);
+        }
+
+        // New embeddings are appended to the existing index
+        let index_path = matches.get_one::<String>("index").unwrap();
+        let mut index = EmbeddingIndex::load_or_new(index_path)
+            .unwrap_or_else(|e| panic!("Failed to read the index at '{}': {}", index_path, e));
+        if let Err(e) = index.check_model() {
+            panic!("{}: {}", index_path, e);
+        }
+        let image_files = get_images_in_folder(Path::new(folder)).unwrap();
+        let n = image_files.len();
+        for (i, path) in image_files.iter().enumerate() {
+            // Running again over the same folder only embeds the new images
+            if index.contains(path) {
+                println!("[{}/{}] {} (already indexed)", i + 1, n, path.display());
+                continue;
+            }
+            let result = if crops {
+                let list_bbox = detect_bbox(&path.to_string_lossy());
+                index.add_detections(path, &list_bbox)
+            } else {
+                index.add_image(path)
+            };
+            match result {
+                Ok(()) => println!("[{}/{}] {}", i + 1, n, path.display()),
+                Err(e) => eprintln!("Failed to embed {}: {}", path.display(), e),
+            }
+        }
+        index.save(index_path).unwrap();
+        println!("{} embeddings in: {}", index.entries.len(), index_path);
+        std::process::exit(0);
+    }
+
+    if let Some(image_path) = matches.get_one::<String>("similar") {
+        load_embedder_by_name(matches.get_one::<String>("embedder").unwrap(), &config);
+        let index_path = matches.get_one::<String>("index").unwrap();
+        let index = EmbeddingIndex::load(index_path)
+            .unwrap_or_else(|e| panic!("Failed to read the index at '{}': {}", index_path, e));
+        if let Err(e) = index.check_model() {
+            panic!("{}: {}", index_path, e);
+        }
+        let k = *matches.get_one::<usize>("k").unwrap();
+
+        for neighbour in index.similar_to(Path::new(image_path), k).unwrap() {
+            match neighbour.bbox {
+                Some(xyxy) => println!(
+                    "{:.4} {} [{}, {}, {}, {}]",
+                    neighbour.similarity,
+                    neighbour.file_path.display(),
+                    xyxy.x1,
+                    xyxy.y1,
+                    xyxy.x2,
+                    xyxy.y2
+                ),
+                None => println!("{:.4} {}", neighbour.similarity, neighbour.file_path.display()),
+            }
+        }
+        std::process::exit(0);
+    }
+
+    if let Some(folder) = matches.get_one::<String>("triage") {
         let model_name = matches.get_one::<String>("model").unwrap();
-        
-        let model_path = format!(
-            "models/{}.bq",
-            model_name.strip_suffix(".bq").unwrap_or(model_name)
-        );
-        let ais: Vec<AI> = get_bqs();
-        let found = ais.iter().any(|ai| ai.get_path().contains(&model_path));
-
-        if found {
-            set_model(model_path, LIST_EPS[1].clone());
-            run_api().await;
-        } else {
-            panic!(
-                "Model path '{}' was not found in any of the registered AI paths.\n\
-        Make sure that the model '{}' (or '{}.bq') exists in the 'models/' directory",
-                model_path,
-                model_name.strip_suffix(".bq").unwrap_or(model_name),
-                model_name.strip_suffix(".bq").unwrap_or(model_name)
+        load_model_by_name(model_name, &config);
+
+        let mut threshold = *matches.get_one::<f32>("threshold").unwrap();
+        // The detector never returns boxes below its own cutoff, so a lower value would be misleading in the report
+        if threshold < CONFIDENCE_THRESHOLD {
+            eprintln!(
+                "--threshold {} is below the model's confidence cutoff, using {}",
+                threshold, CONFIDENCE_THRESHOLD
             );
+            threshold = CONFIDENCE_THRESHOLD;
+        }
+        let mut pred_imgs: Vec<PredImg> = get_images_in_folder(Path::new(folder))
+            .unwrap()
+            .into_iter()
+            .map(|path| PredImg::new_simple(path))
+            .collect();
+
+        if let Some(urls) = matches.get_many::<String>("pool") {
+            let urls: Vec<String> = urls.cloned().collect();
+            let dispatch = Dispatch::from(matches.get_one::<String>("dispatch").unwrap().as_str());
+            let in_flight = *matches.get_one::<usize>("in-flight").unwrap();
+            let api_key = matches.get_one::<String>("api-key").cloned();
+            let paths: Vec<_> = pred_imgs.iter().map(|p| p.file_path.clone()).collect();
+            let (results, reports) = tokio::task::spawn_blocking(move || {
+                // Without a usable key every image is done locally
+                let mut pool = RemotePool::with_api_key(&urls, dispatch, in_flight, api_key.as_deref())
+                    .unwrap_or_else(|e| {
+                        eprintln!("{}, analyzing locally", e);
+                        RemotePool::new(&[], dispatch, in_flight)
+                    });
+                for reason in pool.health_check() {
+                    eprintln!("{}, leaving it out of the pool", reason);
+                }
+                pool.predict(paths, |i, n| println!("[{}/{}]", i, n))
+            })
+            .await
+            .unwrap();
+            for report in reports {
+                println!("{}: {} images, {} failed", report.url, report.done, report.failed);
+            }
+            pred_imgs = results;
+        }
+
+        // Whatever the pool couldn't process is done locally
+        let n = pred_imgs.len();
+        for (i, pred_img) in pred_imgs.iter_mut().enumerate() {
+            if pred_img.wasprocessed {
+                continue;
+            }
+            let path = pred_img.file_path.to_string_lossy().into_owned();
+            pred_img.list_bbox = tokio::task::spawn_blocking(move || detect_bbox(&path))
+                .await
+                .unwrap();
+            pred_img.wasprocessed = true;
+            println!("[{}/{}] {}", i + 1, n, pred_img.file_path.display());
+        }
+
+        let report = matches.get_one::<String>("report").unwrap();
+        if let Some(parent) = Path::new(report).parent() {
+            std::fs::create_dir_all(parent).unwrap();
         }
+        write_triage_report(&pred_imgs, threshold, report).unwrap();
+
+        for (class, count) in count_by_class(&pred_imgs, threshold) {
+            println!("{}: {}", class.as_str(), count);
+        }
+        println!("Report written to: {}", report);
+
+        if let Some(events_path) = matches.get_one::<String>("events") {
+            let gap = TimeDelta::seconds(*matches.get_one::<i64>("event-gap").unwrap());
+            let events = group_events(&pred_imgs, gap);
+            write_csv_events(&pred_imgs, &events, events_path).unwrap();
+            println!("{} events written to: {}", events.len(), events_path);
+        }
+
+        if let Some(output_path) = matches.get_one::<String>("sort-into") {
+            let mode = FileMode::from(matches.get_one::<String>("file-mode").unwrap().as_str());
+            sort_by_triage(&pred_imgs, output_path, threshold, mode).await;
+        }
+        std::process::exit(0);
+    }
+
+    if matches.get_flag("deploy") {
+        let model_name = matches.get_one::<String>("model").unwrap();
+        load_model_by_name(model_name, &config);
+
         // CLI mode
-        
-        let ip_text = format!("http://{}:8791", get_ip());
+        let api = bind_api(&config.api).await.unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
         println!("{}", ASCII_ART);
         println!("Model deployed: {}", model_name);
-        println!("IP Address: {}", ip_text);
+        for url in &api.urls {
+            println!("IP Address: {}", url);
+        }
+        if let Some(path) = &config.api.api_keys {
+            println!("API keys required, from: {}", path);
+        }
+        if let Some(path) = &config.api.jobs_root {
+            println!("Jobs can read: {}", path);
+        }
+        if let Err(e) = serve_api(api).await {
+            eprintln!("The API stopped: {}", e);
+            std::process::exit(1);
+        }
     }
+
+    config
 }
 
 const ASCII_ART: &'static str = r#"
@@ -25,6 +25,20 @@ pub enum Key {
     analyze,
     export,
     analysis,
+    similar_images,
+    build_index,
+    find_similar,
+    zones,
+    connect,
+    server_models,
+    server_unreachable,
+    host,
+    port,
+    certificate,
+    private_key,
+    api_keys,
+    api_key,
+    remote_ep,
 }
 
 pub fn translate(key: Key, lang: &Lang) -> &'static str {
@@ -101,6 +115,61 @@ pub fn translate(key: Key, lang: &Lang) -> &'static str {
             Lang::EN => "Analysis",
             Lang::ES => "Análisis",
         }
+        Key::similar_images => match lang {
+            Lang::EN => "Similar images",
+            Lang::ES => "Imágenes similares",
+        }
+        Key::build_index => match lang {
+            Lang::EN => "Build index",
+            Lang::ES => "Crear índice",
+        }
+        Key::find_similar => match lang {
+            Lang::EN => "Find similar",
+            Lang::ES => "Buscar similares",
+        }
+        Key::zones => match lang {
+            Lang::EN => "Zones",
+            Lang::ES => "Zonas",
+        }
+        Key::connect => match lang {
+            Lang::EN => "Connect",
+            Lang::ES => "Conectar",
+        }
+        Key::server_models => match lang {
+            Lang::EN => "Server models",
+            Lang::ES => "Modelos del servidor",
+        }
+        Key::server_unreachable => match lang {
+            Lang::EN => "Server unreachable, using the local CPU",
+            Lang::ES => "Servidor no disponible, usando la CPU local",
+        }
+        Key::host => match lang {
+            Lang::EN => "Host",
+            Lang::ES => "Host",
+        }
+        Key::port => match lang {
+            Lang::EN => "Port",
+            Lang::ES => "Puerto",
+        }
+        Key::certificate => match lang {
+            Lang::EN => "TLS certificate",
+            Lang::ES => "Certificado TLS",
+        }
+        Key::private_key => match lang {
+            Lang::EN => "TLS key",
+            Lang::ES => "Llave TLS",
+        }
+        Key::api_keys => match lang {
+            Lang::EN => "API keys",
+            Lang::ES => "Llaves de la API",
+        }
+        Key::api_key => match lang {
+            Lang::EN => "API key (optional)",
+            Lang::ES => "Llave de la API (opcional)",
+        }
+        Key::remote_ep => match lang {
+            Lang::EN => "BoquilaHUB Remote",
+            Lang::ES => "BoquilaHUB Remoto",
+        }
     }
 }
-
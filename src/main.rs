@@ -4,7 +4,7 @@ use boquilahub::{cli::run_cli};
 // When compiling natively:
 #[tokio::main]
 async fn main() -> eframe::Result {
-    run_cli().await;
+    let config = run_cli().await;
 
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -20,6 +20,6 @@ async fn main() -> eframe::Result {
     eframe::run_native(
         "BoquilaHUB",
         native_options,
-        Box::new(|_cc| Ok(Box::new(boquilahub::MainApp::new()))),
+        Box::new(|_cc| Ok(Box::new(boquilahub::MainApp::new(config)))),
     )
 }
@@ -0,0 +1,340 @@
+mod support;
+
+use boquilahub::api::auth::{ApiKey, KeyStore, KeyUsage};
+use boquilahub::api::config::ApiConfig;
+use boquilahub::api::health::Health;
+use boquilahub::api::inference::warm_up;
+use boquilahub::api::openapi::ApiDoc;
+use boquilahub::api::remote::RemoteClient;
+use boquilahub::api::rest::{bind_api, check_boquila_hub_api, get_api_urls, router};
+use boquilahub::api::v1::{BatchItem, ErrorResponse, Prediction};
+use reqwest::multipart::{Form, Part};
+use reqwest::Method;
+use std::sync::Arc;
+use support::*;
+use utoipa::OpenApi;
+
+// Serves the API on a free port and returns its address
+async fn serve_with(keys: Option<Arc<KeyStore>>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router(keys)).await.unwrap() });
+    url
+}
+
+async fn serve() -> String {
+    serve_with(None).await
+}
+
+fn image_form(name: &str) -> Form {
+    let data = std::fs::read(fixture_image(name, INPUT_SIZE, INPUT_SIZE)).unwrap();
+    Form::new().part("file", Part::bytes(data).file_name("image.png"))
+}
+
+#[tokio::test]
+async fn predict_returns_detections_and_metadata() {
+    setup();
+    let url = serve().await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/models/fixture/predict", url))
+        .multipart(image_form("api_predict.png"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let prediction: Prediction = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(prediction.model.name, "fixture");
+    assert_eq!(prediction.image.width, INPUT_SIZE);
+    assert_eq!(prediction.detections.len(), 2);
+}
+
+#[tokio::test]
+async fn errors_are_json_with_status_codes() {
+    setup();
+    let url = serve().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/v1/models/missing/predict", url))
+        .multipart(image_form("api_missing.png"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let body: ErrorResponse = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(body.error.code, 404);
+
+    let not_an_image = Form::new().part("file", Part::bytes(b"hello".to_vec()));
+    let response = client
+        .post(format!("{}/v1/models/fixture/predict", url))
+        .multipart(not_an_image)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 422);
+}
+
+#[tokio::test]
+async fn upload_is_still_served() {
+    setup();
+    let url = serve().await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/upload", url))
+        .multipart(image_form("api_upload.png"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert_eq!(serde_json::from_str::<Vec<serde_json::Value>>(&body).unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn busy_port_is_reported() {
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = ApiConfig {
+        host: "127.0.0.1".to_string(),
+        port: taken.local_addr().unwrap().port(),
+        ..ApiConfig::default()
+    };
+    let error = bind_api(&config).await.err().unwrap();
+    assert!(error.contains("already in use"), "{}", error);
+}
+
+#[test]
+fn urls_follow_host_and_tls() {
+    let config = ApiConfig {
+        host: "127.0.0.1".to_string(),
+        port: 9000,
+        tls_cert: Some("cert.pem".to_string()),
+        tls_key: Some("key.pem".to_string()),
+    };
+    assert_eq!(get_api_urls(&config), vec!["https://127.0.0.1:9000"]);
+
+    let urls = get_api_urls(&ApiConfig::default());
+    assert!(urls.contains(&"http://127.0.0.1:8791".to_string()));
+}
+
+#[tokio::test]
+async fn keys_are_required_when_configured() {
+    let key = |label: &str, admin: bool| ApiKey {
+        label: label.to_string(),
+        key: format!("{}-secret", label),
+        rate_per_minute: 0,
+        daily_quota: 0,
+        admin,
+    };
+    let store = KeyStore::new(vec![key("station", false), key("admin", true)]);
+    let url = serve_with(Some(Arc::new(store))).await;
+    let client = reqwest::Client::new();
+
+    // The health check stays open
+    let response = client.get(&url).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client.get(format!("{}/v1/models", url)).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    let response = client
+        .get(format!("{}/v1/models", url))
+        .bearer_auth("station-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let usage_url = format!("{}/v1/admin/usage", url);
+    let response = client.get(&usage_url).bearer_auth("station-secret").send().await.unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = client.get(&usage_url).bearer_auth("admin-secret").send().await.unwrap();
+    let usage: Vec<KeyUsage> = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    let station = usage.iter().find(|u| u.label == "station").unwrap();
+    assert_eq!(station.today, 2);
+}
+
+#[tokio::test]
+async fn batch_reports_each_file() {
+    setup();
+    let url = serve().await;
+    let data = std::fs::read(fixture_image("api_batch.png", INPUT_SIZE, INPUT_SIZE)).unwrap();
+    let form = Form::new()
+        .part("a", Part::bytes(data.clone()).file_name("first.png"))
+        .part("broken", Part::bytes(b"not an image".to_vec()))
+        .part("c", Part::bytes(data).file_name("third.png"));
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/models/fixture/predict/batch", url))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let items: Vec<BatchItem> = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    let names: Vec<&str> = items.iter().map(|item| item.name.as_str()).collect();
+    assert_eq!(names, vec!["first.png", "broken", "third.png"]);
+    assert_eq!(items[0].prediction.as_ref().unwrap().detections.len(), 2);
+    assert_eq!(items[1].error.as_ref().unwrap().code, 422);
+    assert!(items[2].prediction.is_some());
+}
+
+#[tokio::test]
+async fn video_that_cant_be_decoded_is_rejected() {
+    setup();
+    let url = serve().await;
+    let form = Form::new().part("file", Part::bytes(b"not a video".to_vec()).file_name("clip.mp4"));
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/models/fixture/predict/video?format=json", url))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 422);
+}
+
+#[tokio::test]
+async fn render_returns_a_scaled_image() {
+    setup();
+    let url = serve().await;
+    let data = std::fs::read(fixture_image("api_render.png", 200, 100)).unwrap();
+    let form = Form::new().part("file", Part::bytes(data).file_name("image.png"));
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/render?format=png&max_size=50", url))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "image/png");
+
+    let img = image::load_from_memory(&response.bytes().await.unwrap()).unwrap();
+    assert_eq!((img.width(), img.height()), (50, 25));
+}
+
+#[tokio::test]
+async fn openapi_document_describes_the_routes() {
+    let keys = KeyStore::new(vec![ApiKey {
+        label: "station".to_string(),
+        key: "secret".to_string(),
+        rate_per_minute: 0,
+        daily_quota: 0,
+        admin: false,
+    }]);
+    // No key needed to read the docs
+    let url = serve_with(Some(Arc::new(keys))).await;
+    let response = reqwest::get(format!("{}/openapi.json", url)).await.unwrap();
+    assert_eq!(response.status(), 200);
+
+    let doc: serde_json::Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert!(doc["paths"]["/v1/models/{name}/predict"]["post"].is_object());
+    assert!(doc["paths"]["/upload"]["post"].is_object());
+    let xyxyc = &doc["components"]["schemas"]["XYXYc"]["properties"];
+    assert!(xyxyc["xyxy"].is_object() && xyxyc["label"].is_object());
+}
+
+// The routes are written in the routers and again in the document, this keeps them together
+#[tokio::test]
+async fn every_documented_route_is_served() {
+    let keys = KeyStore::new(vec![ApiKey {
+        label: "admin".to_string(),
+        key: "secret".to_string(),
+        rate_per_minute: 0,
+        daily_quota: 0,
+        admin: true,
+    }]);
+    let url = serve_with(Some(Arc::new(keys))).await;
+    let client = reqwest::Client::new();
+
+    let doc = ApiDoc::openapi();
+    for (path, item) in doc.paths.paths.iter() {
+        let operations = [
+            (Method::GET, item.get.is_some()),
+            (Method::POST, item.post.is_some()),
+            (Method::PUT, item.put.is_some()),
+            (Method::DELETE, item.delete.is_some()),
+            (Method::PATCH, item.patch.is_some()),
+        ];
+        let concrete = path.replace("{name}", "fixture").replace("{id}", "none");
+        for (method, _) in operations.into_iter().filter(|(_, documented)| *documented) {
+            let response = client
+                .request(method.clone(), format!("{}{}", url, concrete))
+                .bearer_auth("secret")
+                .send()
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = response.text().await.unwrap();
+            let unrouted = status == 405
+                || (status == 404 && (body.is_empty() || body.contains("No such route")));
+            assert!(!unrouted, "{} {} is documented but not served", method, path);
+        }
+    }
+}
+
+#[tokio::test]
+async fn metrics_count_requests_and_stages() {
+    setup();
+    let url = serve().await;
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/v1/models/fixture/predict", url))
+        .multipart(image_form("api_metrics.png"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client.get(format!("{}/metrics", url)).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    let text = response.text().await.unwrap();
+    assert!(text.contains(
+        r#"boquilahub_http_requests_total{method="POST",route="/v1/models/{name}/predict",status="200"}"#
+    ));
+    for stage in ["decode", "inference", "post_process"] {
+        assert!(text.contains(&format!(r#"boquilahub_prediction_stage_seconds_count{{stage="{}"}}"#, stage)));
+    }
+    assert!(text.contains("boquilahub_detections_total{class="));
+    assert!(text.contains(r#"model="fixture""#));
+    assert!(text.contains("boquilahub_resident_memory_bytes "));
+}
+
+#[tokio::test]
+async fn health_probes_report_the_model() {
+    setup();
+    let url = serve().await;
+    let response = reqwest::get(format!("{}/healthz", url)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let health: Health = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(health.status, "ok");
+    assert_eq!(health.model.unwrap().name, "fixture");
+
+    tokio::task::spawn_blocking(warm_up).await.unwrap().unwrap();
+    let response = reqwest::get(format!("{}/readyz", url)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let health: Health = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+    assert_eq!(health.status, "ready");
+    assert_eq!(health.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(health.ep.as_deref(), Some("CPU"));
+
+    let health = tokio::task::spawn_blocking(move || RemoteClient::new(&url).readiness())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(health.is_ready());
+}
+
+#[tokio::test]
+async fn unreachable_server_is_an_error() {
+    let result = tokio::task::spawn_blocking(|| RemoteClient::new("127.0.0.1:1").readiness())
+        .await
+        .unwrap();
+    assert!(result.is_err());
+    assert!(check_boquila_hub_api("http://127.0.0.1:1").await.is_err());
+}
+
+#[test]
+fn keys_that_cant_be_sent_are_an_error() {
+    assert!(RemoteClient::with_api_key("127.0.0.1:1", Some("bad\nkey")).is_err());
+    assert!(RemoteClient::with_api_key("127.0.0.1:1", Some("good-key")).is_ok());
+}
@@ -0,0 +1,45 @@
+use boquilahub::api::auth::{ApiKey, Denied, KeyStore};
+
+fn key(rate_per_minute: u32, daily_quota: u32) -> ApiKey {
+    ApiKey {
+        label: "station".to_string(),
+        key: "secret".to_string(),
+        rate_per_minute,
+        daily_quota,
+        admin: false,
+    }
+}
+
+#[test]
+fn rate_limit_allows_a_burst_of_one_minute() {
+    let key = key(3, 0);
+    let store = KeyStore::new(vec![key.clone()]);
+    for _ in 0..3 {
+        assert!(store.register(&key).is_ok());
+    }
+    match store.register(&key) {
+        Err(Denied::RateLimited(retry_after)) => assert!(retry_after > 0 && retry_after <= 20),
+        _ => panic!("expected the fourth request to be rate limited"),
+    }
+}
+
+#[test]
+fn daily_quota_is_enforced_and_counted() {
+    let key = key(0, 2);
+    let store = KeyStore::new(vec![key.clone()]);
+    assert!(store.register(&key).is_ok());
+    assert!(store.register(&key).is_ok());
+    assert!(matches!(store.register(&key), Err(Denied::QuotaExceeded)));
+
+    let usage = &store.usage()[0];
+    assert_eq!(usage.today, 2);
+    assert_eq!(usage.rejected, 1);
+}
+
+#[test]
+fn unknown_tokens_are_not_found() {
+    let store = KeyStore::new(vec![key(0, 0)]);
+    assert!(store.find("secret").is_some());
+    assert!(store.find("guess").is_none());
+    assert!(store.find("secre").is_none());
+}
@@ -0,0 +1,47 @@
+mod support;
+
+use boquilahub::api::bq::{export_bq, get_ai_model, import_bq};
+use support::*;
+
+#[test]
+fn import_bq_reads_metadata_and_onnx() {
+    let path = fixture_bq();
+    let (ai, data) = import_bq(&path).unwrap();
+
+    assert_eq!(ai.name, "fixture");
+    assert_eq!(ai.input_width, INPUT_SIZE);
+    assert_eq!(ai.input_height, INPUT_SIZE);
+    assert_eq!(ai.task, "detect");
+    assert_eq!(ai.classes, CLASSES);
+    assert_eq!(data, yolo_onnx(INPUT_SIZE, &DETECTIONS));
+}
+
+#[test]
+fn get_ai_model_matches_import_bq() {
+    let path = fixture_bq();
+    let ai = get_ai_model(&path).unwrap();
+    let (imported, _) = import_bq(&path).unwrap();
+
+    assert_eq!(ai.name, imported.name);
+    assert_eq!(ai.classes, imported.classes);
+}
+
+#[test]
+fn get_ai_model_rejects_other_files() {
+    let path = tmp_path("not-a-model.bq");
+    std::fs::write(&path, b"NOTAMODEL, just some bytes").unwrap();
+
+    assert!(get_ai_model(path.to_str().unwrap()).is_err());
+}
+
+#[test]
+fn export_bq_roundtrip() {
+    let path = tmp_path("roundtrip.bq");
+    let path = path.to_str().unwrap();
+    let onnx = vec![1, 2, 3, 4, 5];
+    export_bq(path, &fixture_ai(), &onnx).unwrap();
+
+    let (ai, data) = import_bq(path).unwrap();
+    assert_eq!(ai.description, fixture_ai().description);
+    assert_eq!(data, onnx);
+}
@@ -0,0 +1,84 @@
+use boquilahub::api::embeddings::{EmbeddingIndex, IndexEntry};
+use boquilahub::api::models::embedder::normalize;
+use std::path::{Path, PathBuf};
+
+fn entry(name: &str, vector: Vec<f32>) -> IndexEntry {
+    IndexEntry {
+        file_path: PathBuf::from(name),
+        bbox: None,
+        vector: normalize(vector),
+    }
+}
+
+fn index() -> EmbeddingIndex {
+    EmbeddingIndex {
+        model: "test".to_string(),
+        entries: vec![
+            entry("a.jpg", vec![1.0, 0.0, 0.0]),
+            entry("b.jpg", vec![0.9, 0.1, 0.0]),
+            entry("c.jpg", vec![0.0, 1.0, 0.0]),
+            entry("d.jpg", vec![0.0, 0.0, 1.0]),
+        ],
+    }
+}
+
+#[test]
+fn nearest_is_sorted_by_similarity() {
+    let neighbours = index().nearest(&normalize(vec![1.0, 0.2, 0.0]), 3);
+
+    assert_eq!(neighbours.len(), 3);
+    assert_eq!(neighbours[0].file_path, Path::new("b.jpg"));
+    assert_eq!(neighbours[1].file_path, Path::new("a.jpg"));
+    assert_eq!(neighbours[2].file_path, Path::new("c.jpg"));
+    assert!(neighbours[0].similarity >= neighbours[1].similarity);
+}
+
+#[test]
+fn similar_to_leaves_the_image_out() {
+    let neighbours = index().similar_to(Path::new("a.jpg"), 2).unwrap();
+
+    assert_eq!(neighbours.len(), 2);
+    assert_eq!(neighbours[0].file_path, Path::new("b.jpg"));
+    assert!(neighbours.iter().all(|n| n.file_path != Path::new("a.jpg")));
+}
+
+#[test]
+fn index_roundtrip() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("index.json");
+    let path = path.to_str().unwrap();
+    index().save(path).unwrap();
+
+    let loaded = EmbeddingIndex::load(path).unwrap();
+    assert_eq!(loaded.model, "test");
+    assert_eq!(loaded.entries.len(), 4);
+    assert_eq!(loaded.entries[2].vector, index().entries[2].vector);
+}
+
+#[test]
+fn remove_drops_every_entry_of_the_image() {
+    let mut index = index();
+    index.entries.push(entry("a.jpg", vec![0.5, 0.5, 0.0]));
+    index.remove(Path::new("a.jpg"));
+
+    assert!(!index.contains(Path::new("a.jpg")));
+    assert!(index.contains(Path::new("b.jpg")));
+    assert_eq!(index.entries.len(), 3);
+}
+
+#[test]
+fn only_a_missing_index_starts_a_new_one() {
+    let missing = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("missing_index.json");
+    let index = EmbeddingIndex::load_or_new(missing.to_str().unwrap()).unwrap();
+    assert!(index.entries.is_empty());
+
+    let corrupt = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("corrupt_index.json");
+    std::fs::write(&corrupt, "{\"model\": \"test\", \"entr").unwrap();
+    assert!(EmbeddingIndex::load_or_new(corrupt.to_str().unwrap()).is_err());
+}
+
+#[test]
+fn index_of_another_embedder_is_rejected() {
+    // No embedder is loaded in this test binary
+    let error = index().check_model().unwrap_err();
+    assert!(error.to_string().contains("'test'"), "{}", error);
+}
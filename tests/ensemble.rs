@@ -0,0 +1,91 @@
+mod support;
+
+use boquilahub::api::abstractions::{BoundingBoxTrait, BoundingBoxTraitC, XYXYc, XYXY};
+use boquilahub::api::bq::export_bq;
+use boquilahub::api::ensemble::{fuse, Fusion, Pipeline};
+use support::*;
+
+fn bbox(x1: f32, prob: f32, label: &str, class_id: u16, model: &str) -> XYXYc {
+    XYXYc::new(XYXY::new(x1, 0.0, x1 + 10.0, 10.0, prob, class_id), label.to_string())
+        .with_model(model)
+}
+
+fn pipeline(fusion: Fusion) -> Pipeline {
+    Pipeline::new("test".to_string(), vec!["a".to_string(), "b".to_string()], fusion)
+}
+
+#[test]
+fn wbf_merges_overlapping_boxes_of_the_same_class() {
+    let boxes = fuse(
+        vec![vec![bbox(0.0, 0.9, "deer", 0, "a")], vec![bbox(1.0, 0.3, "deer", 0, "b")]],
+        &pipeline(Fusion::WBF),
+    );
+    assert_eq!(boxes.len(), 1);
+
+    // Weighted by confidence: (0 * 0.9 + 1 * 0.3) / 1.2
+    let (x1, _, x2, _) = boxes[0].xyxy.get_coords();
+    assert!((x1 - 0.25).abs() < 1e-5);
+    assert!((x2 - 10.25).abs() < 1e-5);
+    // Both models found it, so it's the mean confidence
+    assert!((boxes[0].xyxy.prob - 0.6).abs() < 1e-5);
+    assert_eq!(boxes[0].model.as_deref(), Some("a+b"));
+}
+
+#[test]
+fn wbf_lowers_the_confidence_of_boxes_found_by_one_model() {
+    let boxes = fuse(
+        vec![vec![bbox(0.0, 0.8, "deer", 0, "a")], vec![]],
+        &pipeline(Fusion::WBF),
+    );
+    assert_eq!(boxes.len(), 1);
+    assert!((boxes[0].xyxy.prob - 0.4).abs() < 1e-5);
+}
+
+#[test]
+fn different_classes_are_not_merged() {
+    let predictions = vec![vec![bbox(0.0, 0.9, "deer", 0, "a")], vec![bbox(0.0, 0.8, "fox", 1, "b")]];
+    assert_eq!(fuse(predictions.clone(), &pipeline(Fusion::WBF)).len(), 2);
+    assert_eq!(fuse(predictions.clone(), &pipeline(Fusion::None)).len(), 2);
+    // Each class only has one vote
+    assert!(fuse(predictions, &pipeline(Fusion::Voting)).is_empty());
+}
+
+#[test]
+fn voting_needs_min_votes_models() {
+    let predictions = vec![
+        vec![bbox(0.0, 0.9, "deer", 0, "a"), bbox(50.0, 0.9, "deer", 0, "a")],
+        vec![bbox(1.0, 0.7, "deer", 0, "b")],
+    ];
+    let boxes = fuse(predictions.clone(), &pipeline(Fusion::Voting));
+    assert_eq!(boxes.len(), 1);
+    assert!((boxes[0].xyxy.prob - 0.9).abs() < 1e-6);
+    assert_eq!(boxes[0].model.as_deref(), Some("a+b"));
+
+    let mut lenient = pipeline(Fusion::Voting);
+    lenient.min_votes = 1;
+    assert_eq!(fuse(predictions, &lenient).len(), 2);
+}
+
+#[test]
+fn to_ai_reads_members_by_file_name() {
+    let folder = tmp_path(&format!("ensemble-{}", std::process::id()));
+    std::fs::create_dir_all(&folder).unwrap();
+    let folder = folder.to_str().unwrap();
+
+    // The file name and the name in the metadata don't have to match
+    let mut ai = fixture_ai();
+    ai.name = "renamed".to_string();
+    ai.classes = vec!["tapir".to_string()];
+    let onnx = yolo_onnx(INPUT_SIZE, &DETECTIONS);
+    export_bq(&format!("{}/tapirs.bq", folder), &ai, &onnx).unwrap();
+    export_bq(&format!("{}/general.bq", folder), &fixture_ai(), &onnx).unwrap();
+
+    let pipeline = Pipeline::new(
+        "both".to_string(),
+        vec!["tapirs".to_string(), "general.bq".to_string()],
+        Fusion::WBF,
+    );
+    let merged = pipeline.to_ai_in(folder);
+    assert_eq!(merged.classes, vec!["tapir", "animal", "person"]);
+    assert_eq!(merged.input_width, INPUT_SIZE);
+}
@@ -0,0 +1,28 @@
+use boquilahub::api::eps::{available_eps, fallback_chain, get_ep_by_name, unprobed_eps, LIST_EPS};
+
+#[test]
+fn fallback_chain_ends_with_the_cpu() {
+    let tensorrt = get_ep_by_name(LIST_EPS, "TensorRT");
+    let chain: Vec<&str> = fallback_chain(&tensorrt).iter().map(|ep| ep.name).collect();
+    assert_eq!(chain, vec!["TensorRT", "CUDA", "CPU"]);
+
+    for ep in LIST_EPS {
+        assert_eq!(fallback_chain(ep).last().unwrap().name, "CPU");
+    }
+}
+
+#[test]
+fn cpu_is_always_available() {
+    let eps = available_eps();
+    assert_eq!(eps[0].name, "CPU");
+    #[cfg(not(feature = "openvino"))]
+    assert!(get_ep_by_name(LIST_EPS, "OpenVINO").check().is_err());
+}
+
+#[test]
+fn unprobed_eps_are_also_available() {
+    let available: Vec<&str> = available_eps().iter().map(|ep| ep.name).collect();
+    let unprobed: Vec<&str> = unprobed_eps().iter().map(|ep| ep.name).collect();
+    assert_eq!(unprobed[0], "CPU");
+    assert!(unprobed.iter().all(|name| available.contains(name)));
+}
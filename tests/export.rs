@@ -0,0 +1,59 @@
+mod support;
+
+use boquilahub::api::abstractions::{BoundingBoxTrait, BoundingBoxTraitC, PredImg, XYXYc, XYXY};
+use boquilahub::api::export::{write_csv, write_csv2};
+use std::path::PathBuf;
+use support::*;
+
+fn pred_imgs() -> Vec<PredImg> {
+    let with_boxes = fixture_image("export-1.png", 32, 32);
+    let empty = fixture_image("export-2.png", 32, 32);
+    vec![
+        PredImg::new(
+            PathBuf::from(&with_boxes),
+            vec![
+                XYXYc::new(XYXY::new(1.0, 2.0, 3.0, 4.0, 0.9, 0), "animal".to_string()),
+                XYXYc::new(XYXY::new(5.0, 6.0, 7.0, 8.0, 0.7, 1), "person".to_string()),
+            ],
+            true,
+        ),
+        PredImg::new(PathBuf::from(&empty), vec![], true),
+    ]
+}
+
+fn read_rows(path: &str) -> Vec<csv::StringRecord> {
+    csv::Reader::from_path(path)
+        .unwrap()
+        .records()
+        .map(|r| r.unwrap())
+        .collect()
+}
+
+#[test]
+fn write_csv_has_one_row_per_box() {
+    let output = tmp_path("boxes.csv");
+    let output = output.to_str().unwrap();
+    write_csv(pred_imgs(), output).unwrap();
+
+    let rows = read_rows(output);
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0][0].ends_with("export-1.png"));
+    assert_eq!(&rows[0][1], "1");
+    assert_eq!(&rows[0][4], "4");
+    assert_eq!(&rows[0][5], "0");
+    assert_eq!(&rows[0][6], "0.9");
+    assert_eq!(&rows[1][5], "1");
+}
+
+#[test]
+fn write_csv2_has_one_row_per_image() {
+    let output = tmp_path("images.csv");
+    let output = output.to_str().unwrap();
+    write_csv2(pred_imgs(), output).unwrap();
+
+    let rows = read_rows(output);
+    assert_eq!(rows.len(), 2);
+    assert_eq!(&rows[0][1], "2");
+    assert_eq!(&rows[1][1], "0");
+    assert_eq!(&rows[1][2], "");
+}
@@ -0,0 +1,20 @@
+mod support;
+
+use boquilahub::api::health::{liveness, readiness};
+use boquilahub::api::inference::warm_up;
+use support::*;
+
+// One test, the model is process-wide and it starts without one
+#[test]
+fn readiness_waits_for_a_warm_model() {
+    assert_eq!(readiness().status, "no_model");
+    assert_eq!(liveness().status, "ok");
+
+    setup();
+    let health = readiness();
+    assert_eq!(health.status, "warming_up");
+    assert_eq!(health.model.unwrap().name, "fixture");
+
+    warm_up().unwrap();
+    assert!(readiness().is_ready());
+}
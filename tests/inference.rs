@@ -0,0 +1,62 @@
+mod support;
+
+use boquilahub::api::abstractions::{BoundingBoxTrait, XYXY};
+use boquilahub::api::config::SessionConfig;
+use boquilahub::api::inference::{detect_bbox, detect_bbox_from_imgbuf, model_cache_key};
+use support::*;
+
+#[test]
+fn detect_bbox_keeps_confident_boxes_after_nms() {
+    setup();
+    let img = fixture_image("detect.png", INPUT_SIZE, INPUT_SIZE);
+    let mut boxes = detect_bbox(&img);
+    boxes.sort_by(|a, b| b.xyxy.prob.partial_cmp(&a.xyxy.prob).unwrap());
+
+    // The second animal overlaps the first one and the last anchor is below the threshold
+    assert_eq!(boxes.len(), 2);
+    assert_eq!(boxes[0].label, "animal");
+    assert_eq!(boxes[0].xyxy.class_id, 0);
+    assert!((boxes[0].xyxy.prob - 0.9).abs() < 1e-6);
+    assert_eq!(boxes[1].label, "person");
+    assert_eq!(boxes[1].xyxy.class_id, 1);
+    assert!((boxes[1].xyxy.prob - 0.7).abs() < 1e-6);
+}
+
+#[test]
+fn detect_bbox_scales_to_image_size() {
+    setup();
+    let img = image::RgbImage::new(INPUT_SIZE * 2, INPUT_SIZE * 2);
+    let boxes = detect_bbox_from_imgbuf(&img);
+    let animal = boxes.iter().find(|b| b.label == "animal").unwrap();
+
+    // xc = 16, w = 16 at 64px is x1 = 16, x2 = 48 at 128px
+    let (x1, y1, x2, y2) = animal.xyxy.get_coords();
+    assert!((x1 - 16.0).abs() < 1e-3);
+    assert!((y1 - 16.0).abs() < 1e-3);
+    assert!((x2 - 48.0).abs() < 1e-3);
+    assert!((y2 - 48.0).abs() < 1e-3);
+}
+
+#[test]
+fn iou_of_boxes() {
+    let a = XYXY::new(0.0, 0.0, 10.0, 10.0, 0.9, 0);
+    let b = XYXY::new(5.0, 0.0, 15.0, 10.0, 0.8, 0);
+    let far = XYXY::new(20.0, 20.0, 30.0, 30.0, 0.8, 0);
+
+    assert!((a.iou(&a) - 1.0).abs() < 1e-6);
+    assert!((a.iou(&b) - 50.0 / 150.0).abs() < 1e-6);
+    assert_eq!(a.iou(&far), 0.0);
+}
+
+#[test]
+fn model_cache_key_is_stable() {
+    let config = SessionConfig::default();
+    // SHA-256 of the bytes followed by the options, so it can't change with the toolchain
+    assert_eq!(model_cache_key(b"model", &config), "8f8d67410d7f7c5f");
+
+    let level_1 = SessionConfig {
+        optimization_level: 1,
+        ..SessionConfig::default()
+    };
+    assert_ne!(model_cache_key(b"model", &level_1), model_cache_key(b"model", &config));
+}
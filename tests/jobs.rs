@@ -0,0 +1,136 @@
+mod support;
+
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use boquilahub::api::jobs::{
+    cancel_job, create_job, download, get_job, get_results, set_jobs_root, DownloadQuery, Job,
+    JobKind, JobOptions, JobState, JOBS_DIR,
+};
+use chrono::Local;
+use std::time::{Duration, Instant};
+use support::*;
+
+fn setup_jobs() {
+    setup();
+    set_jobs_root(Some(env!("CARGO_TARGET_TMPDIR")));
+}
+
+fn wait_until_finished(id: &str) -> JobState {
+    let start = Instant::now();
+    loop {
+        let job = get_job(id).unwrap();
+        if job.state.is_finished() || start.elapsed() > Duration::from_secs(30) {
+            return job.state;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn folder_job_runs_in_the_background() {
+    setup_jobs();
+    let folder = tmp_path("job_folder");
+    std::fs::create_dir_all(&folder).unwrap();
+    for i in 0..3 {
+        let image = fixture_image(&format!("job_{}.png", i), INPUT_SIZE, INPUT_SIZE);
+        std::fs::rename(image, folder.join(format!("{}.png", i))).unwrap();
+    }
+
+    let job = create_job(folder.to_str().unwrap(), JobOptions::default()).unwrap();
+    assert_eq!(job.state, JobState::Queued);
+    assert_eq!(wait_until_finished(&job.id), JobState::Completed);
+
+    let job = get_job(&job.id).unwrap();
+    assert_eq!((job.processed, job.total), (3, 3));
+    let results = get_results(&job);
+    assert_eq!(results.images.len(), 3);
+    assert!(results.images.iter().all(|image| image.detections.len() == 2));
+
+    // Finished jobs can't be cancelled
+    assert!(cancel_job(&job.id).is_err());
+}
+
+#[test]
+fn unknown_paths_are_rejected() {
+    assert!(create_job("does/not/exist", JobOptions::default()).is_err());
+}
+
+#[test]
+fn paths_outside_the_jobs_root_are_forbidden() {
+    setup_jobs();
+    let error = create_job("src", JobOptions::default()).unwrap_err();
+    assert_eq!(error.status, StatusCode::FORBIDDEN);
+    // `..` can't be used to leave it
+    let escape = tmp_path("..").join("..").join("src");
+    let error = create_job(escape.to_str().unwrap(), JobOptions::default()).unwrap_err();
+    assert_eq!(error.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn only_videos_can_be_downloaded() {
+    setup_jobs();
+    let folder = tmp_path("job_download_folder");
+    std::fs::create_dir_all(&folder).unwrap();
+    let job = create_job(folder.to_str().unwrap(), JobOptions::default()).unwrap();
+    let error = download(Path(job.id), Query(DownloadQuery::default()))
+        .await
+        .unwrap_err();
+    assert_eq!(error.status, StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn unreadable_images_get_an_error_and_the_job_goes_on() {
+    setup_jobs();
+    let folder = tmp_path("job_broken_folder");
+    std::fs::create_dir_all(&folder).unwrap();
+    let image = fixture_image("job_good.png", INPUT_SIZE, INPUT_SIZE);
+    std::fs::rename(image, folder.join("a.png")).unwrap();
+    std::fs::write(folder.join("b.jpg"), "not an image").unwrap();
+
+    let job = create_job(folder.to_str().unwrap(), JobOptions::default()).unwrap();
+    assert_eq!(wait_until_finished(&job.id), JobState::Completed);
+
+    let results = get_results(&get_job(&job.id).unwrap());
+    assert_eq!(results.images.len(), 2);
+    assert!(results.images[0].error.is_none());
+    assert_eq!(results.images[0].detections.len(), 2);
+    assert!(results.images[1].error.is_some());
+    assert!(results.images[1].detections.is_empty());
+}
+
+#[test]
+fn eta_after_a_resume_only_counts_the_work_since() {
+    // 50 images were done before the restart, 10 more in the 10 seconds since
+    let job = Job {
+        id: "resumed".to_string(),
+        kind: JobKind::Folder,
+        path: "folder".to_string(),
+        options: JobOptions::default(),
+        state: JobState::Running,
+        processed: 60,
+        processed_at_start: 50,
+        total: 100,
+        created_at: Local::now(),
+        started_at: Some(Local::now() - chrono::Duration::seconds(10)),
+        finished_at: None,
+        error: None,
+    };
+    let eta = job.eta_secs().unwrap();
+    assert!((eta - 40.0).abs() < 1.0, "{}", eta);
+
+    let just_resumed = Job { processed: 50, ..job };
+    assert!(just_resumed.eta_secs().is_none());
+}
+
+#[test]
+fn uploads_are_deleted_when_their_job_finishes() {
+    setup_jobs();
+    let uploads = std::path::Path::new(JOBS_DIR).join("uploads");
+    std::fs::create_dir_all(&uploads).unwrap();
+    let upload = uploads.join("job_upload_test.mp4");
+    std::fs::write(&upload, "not a video").unwrap();
+
+    let job = create_job(upload.to_str().unwrap(), JobOptions::default()).unwrap();
+    assert!(wait_until_finished(&job.id).is_finished());
+    assert!(!upload.exists());
+}
@@ -0,0 +1,58 @@
+use boquilahub::api::config::MotionConfig;
+use boquilahub::api::metrics::{count_gated_frame, render};
+use boquilahub::api::motion::MotionDetector;
+use image::{Rgb, RgbImage};
+
+fn config(cooldown_frames: u32) -> MotionConfig {
+    MotionConfig {
+        enabled: true,
+        cooldown_frames,
+        ..MotionConfig::default()
+    }
+}
+
+fn scene(animal: Option<(u32, u32)>) -> RgbImage {
+    let mut img = RgbImage::from_pixel(320, 240, Rgb([90, 110, 80]));
+    if let Some((x, y)) = animal {
+        for dx in 0..60 {
+            for dy in 0..40 {
+                img.put_pixel(x + dx, y + dy, Rgb([200, 160, 120]));
+            }
+        }
+    }
+    img
+}
+
+#[test]
+fn static_scene_is_skipped() {
+    let mut motion = MotionDetector::new(config(0));
+    // Nothing to compare the first frame against
+    assert!(motion.should_infer(&scene(None)));
+    for _ in 0..5 {
+        assert!(!motion.should_infer(&scene(None)));
+    }
+    assert!(motion.motion_score(&scene(None)) < 0.001);
+}
+
+#[test]
+fn motion_opens_the_gate_for_the_cooldown() {
+    let mut motion = MotionDetector::new(config(2));
+    motion.should_infer(&scene(None));
+    motion.should_infer(&scene(None));
+
+    assert!(motion.should_infer(&scene(Some((100, 100)))));
+    // The scene goes back to the background, the cool-down keeps the detector running
+    assert!(motion.should_infer(&scene(None)));
+    assert!(motion.should_infer(&scene(None)));
+    assert!(!motion.should_infer(&scene(None)));
+}
+
+#[test]
+fn skipped_frames_are_exposed_as_metrics() {
+    count_gated_frame(true, 0.0);
+    count_gated_frame(false, 0.25);
+    let text = render().unwrap();
+    assert!(text.contains(r#"boquilahub_video_frames_total{result="inferred"}"#));
+    assert!(text.contains(r#"boquilahub_video_frames_total{result="skipped"}"#));
+    assert!(text.contains("boquilahub_motion_cpu_saved_seconds_total 0.25"));
+}
@@ -0,0 +1,95 @@
+mod support;
+
+use axum::{http::StatusCode, routing::post, Router};
+use boquilahub::api::abstractions::PredImg;
+use boquilahub::api::remote_pool::{Dispatch, RemotePool, ServerReport};
+use boquilahub::api::rest::router;
+use std::path::PathBuf;
+use support::*;
+
+// Every test gets its own files, the tests run at the same time
+fn images(test: &str, n: usize) -> Vec<PathBuf> {
+    (0..n)
+        .map(|i| {
+            let name = format!("pool_{}_{}.jpg", test, i);
+            PathBuf::from(fixture_image(&name, INPUT_SIZE, INPUT_SIZE))
+        })
+        .collect()
+}
+
+// Serves `app` on a free port and returns its address
+async fn serve(app: Router) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+// A server that is up but fails every prediction
+fn broken() -> Router {
+    Router::new().route("/upload", post(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+}
+
+async fn predict(pool: RemotePool, paths: Vec<PathBuf>) -> (Vec<PredImg>, Vec<ServerReport>) {
+    tokio::task::spawn_blocking(move || pool.predict(paths, |_, _| {}))
+        .await
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn images_are_split_across_servers_and_merged_in_order() {
+    setup();
+    let urls = vec![serve(router(None)).await, serve(router(None)).await];
+    let paths = images("split", 8);
+    let pool = RemotePool::new(&urls, Dispatch::RoundRobin, 2);
+    let (pred_imgs, reports) = predict(pool, paths.clone()).await;
+
+    let returned: Vec<PathBuf> = pred_imgs.iter().map(|p| p.file_path.clone()).collect();
+    assert_eq!(returned, paths);
+    assert!(pred_imgs.iter().all(|p| p.wasprocessed && p.list_bbox.len() == 2));
+    assert_eq!(reports.iter().map(|r| r.done).collect::<Vec<_>>(), vec![4, 4]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn failed_images_are_retried_on_another_server() {
+    setup();
+    let urls = vec![serve(broken()).await, serve(router(None)).await];
+    let paths = images("retry", 6);
+    let pool = RemotePool::new(&urls, Dispatch::RoundRobin, 1);
+    let (pred_imgs, reports) = predict(pool, paths.clone()).await;
+
+    let returned: Vec<PathBuf> = pred_imgs.iter().map(|p| p.file_path.clone()).collect();
+    assert_eq!(returned, paths);
+    assert!(pred_imgs.iter().all(|p| p.wasprocessed));
+    assert_eq!(reports[0].done, 0);
+    assert!(reports[0].failed > 0);
+    assert_eq!(reports[1].done, 6);
+}
+
+#[test]
+fn unreachable_servers_leave_images_unprocessed_in_order() {
+    let paths = images("unreachable", 6);
+    let urls = vec!["127.0.0.1:1".to_string(), "127.0.0.1:2".to_string()];
+    let pool = RemotePool::new(&urls, Dispatch::RoundRobin, 2);
+    let (pred_imgs, reports) = pool.predict(paths.clone(), |_, _| {});
+
+    let returned: Vec<PathBuf> = pred_imgs.iter().map(|p| p.file_path.clone()).collect();
+    assert_eq!(returned, paths);
+    assert!(pred_imgs.iter().all(|p| !p.wasprocessed));
+    assert!(reports.iter().all(|r| r.done == 0));
+}
+
+#[test]
+fn empty_pool_returns_every_image() {
+    let paths = images("empty", 3);
+    let pool = RemotePool::new(&[], Dispatch::LeastLoaded, 4);
+    let (pred_imgs, reports) = pool.predict(paths, |_, _| {});
+    assert_eq!(pred_imgs.len(), 3);
+    assert!(reports.is_empty());
+}
+
+#[test]
+fn dispatch_from_cli_value() {
+    assert_eq!(Dispatch::from("round-robin"), Dispatch::RoundRobin);
+    assert_eq!(Dispatch::from("least-loaded"), Dispatch::LeastLoaded);
+}
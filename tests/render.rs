@@ -0,0 +1,28 @@
+use boquilahub::api::abstractions::{BoundingBoxTrait, BoundingBoxTraitC, XYXYc, XYXY};
+use boquilahub::api::render::draw_bbox_from_imgbuf;
+use image::{Rgb, RgbImage};
+
+const GRAY: Rgb<u8> = Rgb([128, 128, 128]);
+
+#[test]
+fn draws_box_with_class_color() {
+    let mut img = RgbImage::from_pixel(128, 128, GRAY);
+    let bbox = XYXYc::new(
+        XYXY::new(16.0, 16.0, 48.0, 48.0, 0.9, 0),
+        "animal".to_string(),
+    );
+    draw_bbox_from_imgbuf(&mut img, &vec![bbox]);
+
+    // Left edge, below the label, in the color of the first class
+    assert_eq!(*img.get_pixel(16, 40), Rgb([255, 0, 0]));
+    // Inside the box nothing changes
+    assert_eq!(*img.get_pixel(32, 40), GRAY);
+}
+
+#[test]
+fn no_predictions_leaves_image_untouched() {
+    let mut img = RgbImage::from_pixel(64, 64, GRAY);
+    draw_bbox_from_imgbuf(&mut img, &vec![]);
+
+    assert!(img.pixels().all(|p| *p == GRAY));
+}
@@ -0,0 +1,106 @@
+use boquilahub::api::abstractions::{BoundingBoxTrait, BoundingBoxTraitC, XYXYc, XYXY};
+use boquilahub::api::config::SmoothingConfig;
+use boquilahub::api::smoothing::{interpolate, TemporalSmoother};
+use boquilahub::api::tracking::Tracker;
+
+fn tracked(id: Option<u32>, x1: f32, label: &str, class_id: u16, prob: f32) -> XYXYc {
+    let mut bbox = XYXYc::new(
+        XYXY::new(x1, 0.0, x1 + 10.0, 10.0, prob, class_id),
+        label.to_string(),
+    );
+    bbox.track_id = id;
+    bbox
+}
+
+fn smoother(window: usize) -> TemporalSmoother {
+    TemporalSmoother::new(SmoothingConfig {
+        enabled: true,
+        window,
+    })
+}
+
+#[test]
+fn labels_are_voted_over_the_window() {
+    let mut smoother = smoother(5);
+    let labels = ["deer", "deer", "puma", "deer", "puma"];
+    let mut voted = Vec::new();
+    for (i, label) in labels.iter().enumerate() {
+        let class_id = if *label == "deer" { 0 } else { 1 };
+        let output = smoother.smooth(vec![tracked(Some(1), i as f32, label, class_id, 0.8)]);
+        voted.push((output[0].label.clone(), output[0].xyxy.class_id));
+    }
+    assert!(voted.iter().all(|v| *v == ("deer".to_string(), 0)));
+}
+
+#[test]
+fn window_length_limits_the_vote() {
+    let mut smoother = smoother(2);
+    smoother.smooth(vec![tracked(Some(1), 0.0, "deer", 0, 0.9)]);
+    smoother.smooth(vec![tracked(Some(1), 0.0, "deer", 0, 0.9)]);
+    smoother.smooth(vec![tracked(Some(1), 0.0, "puma", 1, 0.9)]);
+    let output = smoother.smooth(vec![tracked(Some(1), 0.0, "puma", 1, 0.9)]);
+    assert_eq!(output[0].label, "puma");
+}
+
+#[test]
+fn untracked_detections_show_from_their_second_frame() {
+    let mut smoother = smoother(5);
+    let output = smoother.smooth(vec![
+        tracked(Some(1), 0.0, "deer", 0, 0.9),
+        tracked(None, 50.0, "deer", 0, 0.9),
+    ]);
+    assert_eq!(output.len(), 1);
+    assert_eq!(output[0].track_id, Some(1));
+
+    let output = smoother.smooth(vec![
+        tracked(Some(1), 0.0, "deer", 0, 0.9),
+        tracked(None, 52.0, "deer", 0, 0.9),
+    ]);
+    assert_eq!(output.len(), 2);
+    assert_eq!(output[1].track_id, None);
+}
+
+#[test]
+fn detections_show_before_their_track_is_confirmed() {
+    // Tracks need 3 hits for an id, the animal is only there for 2 frames
+    let mut tracker = Tracker::default();
+    let mut smoother = smoother(5);
+    let mut shown = Vec::new();
+    for frame in 0..2 {
+        let tracked = tracker.update(vec![tracked(None, 0.0, "deer", 0, 0.9)], frame as f64);
+        shown.push(smoother.smooth(tracked));
+    }
+    assert!(shown[0].is_empty());
+    assert_eq!(shown[1].len(), 1);
+    assert_eq!(shown[1][0].label, "deer");
+}
+
+#[test]
+fn one_frame_detections_never_show() {
+    let mut tracker = Tracker::default();
+    let mut smoother = smoother(5);
+    // A false positive in one frame, then another one somewhere else
+    let frames = [
+        vec![tracked(None, 0.0, "deer", 0, 0.9)],
+        vec![],
+        vec![tracked(None, 60.0, "puma", 1, 0.9)],
+        vec![],
+    ];
+    for (frame, detections) in frames.into_iter().enumerate() {
+        let tracked = tracker.update(detections, frame as f64);
+        assert!(smoother.smooth(tracked).is_empty());
+    }
+}
+
+#[test]
+fn boxes_are_interpolated_between_inference_frames() {
+    let prev = vec![tracked(Some(1), 0.0, "deer", 0, 0.8), tracked(Some(2), 100.0, "deer", 0, 0.8)];
+    let next = vec![tracked(Some(1), 20.0, "deer", 0, 0.8)];
+
+    let boxes = interpolate(&prev, &next, 0.25);
+    assert_eq!(boxes.len(), 2);
+    assert_eq!(boxes[0].xyxy.x1, 5.0);
+    assert_eq!(boxes[0].xyxy.x2, 15.0);
+    // Not in the next frame, held in place
+    assert_eq!(boxes[1].xyxy.x1, 100.0);
+}
@@ -0,0 +1,227 @@
+// Test fixtures: tiny ONNX graphs written by hand, so the tests don't need any real model
+//
+// The graph takes the usual "images" input and always returns the same "output0",
+// shaped [1, 4 + classes, anchors] like a YOLO detector
+#![allow(dead_code)]
+use boquilahub::api::abstractions::AI;
+use boquilahub::api::bq::export_bq;
+use boquilahub::api::config::SessionConfig;
+use boquilahub::api::eps::LIST_EPS;
+use boquilahub::api::inference::set_model;
+use std::path::PathBuf;
+use std::sync::{Once, OnceLock};
+
+pub const INPUT_SIZE: u32 = 64;
+pub const CLASSES: [&str; 2] = ["animal", "person"];
+
+// One row per anchor: xc, yc, w, h (in input pixels), then one score per class
+pub const DETECTIONS: [[f32; 6]; 4] = [
+    [16.0, 16.0, 16.0, 16.0, 0.9, 0.1], // animal
+    [17.0, 16.0, 16.0, 16.0, 0.8, 0.05], // same animal, removed by NMS
+    [48.0, 48.0, 12.0, 20.0, 0.1, 0.7], // person
+    [40.0, 10.0, 8.0, 8.0, 0.2, 0.3],   // below the confidence threshold
+];
+
+// Protobuf wire format, just what ONNX needs
+fn varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn field_varint(buf: &mut Vec<u8>, field: u64, value: u64) {
+    varint(buf, field << 3);
+    varint(buf, value);
+}
+
+fn field_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    varint(buf, (field << 3) | 2);
+    varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn field_str(buf: &mut Vec<u8>, field: u64, s: &str) {
+    field_bytes(buf, field, s.as_bytes());
+}
+
+// TensorProto with FLOAT data
+fn tensor(name: &str, dims: &[i64], values: &[f32]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for dim in dims {
+        field_varint(&mut buf, 1, *dim as u64);
+    }
+    field_varint(&mut buf, 2, 1);
+    field_str(&mut buf, 8, name);
+    let raw: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+    field_bytes(&mut buf, 9, &raw);
+    buf
+}
+
+// ValueInfoProto of a FLOAT tensor
+fn value_info(name: &str, dims: &[i64]) -> Vec<u8> {
+    let mut shape = Vec::new();
+    for dim in dims {
+        let mut dimension = Vec::new();
+        field_varint(&mut dimension, 1, *dim as u64);
+        field_bytes(&mut shape, 1, &dimension);
+    }
+    let mut tensor_type = Vec::new();
+    field_varint(&mut tensor_type, 1, 1);
+    field_bytes(&mut tensor_type, 2, &shape);
+    let mut type_proto = Vec::new();
+    field_bytes(&mut type_proto, 1, &tensor_type);
+
+    let mut buf = Vec::new();
+    field_str(&mut buf, 1, name);
+    field_bytes(&mut buf, 2, &type_proto);
+    buf
+}
+
+// AttributeProto of type INT
+fn int_attribute(name: &str, value: i64) -> Vec<u8> {
+    let mut buf = Vec::new();
+    field_str(&mut buf, 1, name);
+    field_varint(&mut buf, 3, value as u64);
+    field_varint(&mut buf, 20, 2);
+    buf
+}
+
+fn node(op_type: &str, inputs: &[&str], outputs: &[&str], attributes: &[Vec<u8>]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for input in inputs {
+        field_str(&mut buf, 1, input);
+    }
+    for output in outputs {
+        field_str(&mut buf, 2, output);
+    }
+    field_str(&mut buf, 3, &format!("{}_{}", op_type, outputs[0]));
+    field_str(&mut buf, 4, op_type);
+    for attribute in attributes {
+        field_bytes(&mut buf, 5, attribute);
+    }
+    buf
+}
+
+// output0 = predictions + 0 * sum(images), so the input is still part of the graph
+pub fn yolo_onnx(input_size: u32, detections: &[[f32; 6]]) -> Vec<u8> {
+    let size = input_size as i64;
+    let rows = detections[0].len();
+    let anchors = detections.len();
+
+    // [1, rows, anchors], each anchor is a column
+    let mut predictions = Vec::with_capacity(rows * anchors);
+    for k in 0..rows {
+        for detection in detections {
+            predictions.push(detection[k]);
+        }
+    }
+
+    let mut graph = Vec::new();
+    field_bytes(
+        &mut graph,
+        1,
+        &node("ReduceSum", &["images"], &["sum"], &[int_attribute("keepdims", 0)]),
+    );
+    field_bytes(&mut graph, 1, &node("Mul", &["sum", "zero"], &["zeroed"], &[]));
+    field_bytes(
+        &mut graph,
+        1,
+        &node("Add", &["predictions", "zeroed"], &["output0"], &[]),
+    );
+    field_str(&mut graph, 2, "fixture");
+    field_bytes(&mut graph, 5, &tensor("zero", &[], &[0.0]));
+    field_bytes(
+        &mut graph,
+        5,
+        &tensor("predictions", &[1, rows as i64, anchors as i64], &predictions),
+    );
+    field_bytes(&mut graph, 11, &value_info("images", &[1, 3, size, size]));
+    field_bytes(
+        &mut graph,
+        12,
+        &value_info("output0", &[1, rows as i64, anchors as i64]),
+    );
+
+    let mut opset = Vec::new();
+    field_str(&mut opset, 1, "");
+    field_varint(&mut opset, 2, 13);
+
+    let mut model = Vec::new();
+    field_varint(&mut model, 1, 7);
+    field_str(&mut model, 2, "boquilahub-tests");
+    field_bytes(&mut model, 7, &graph);
+    field_bytes(&mut model, 8, &opset);
+    model
+}
+
+pub fn fixture_ai() -> AI {
+    AI::new(
+        "fixture".to_string(),
+        0.1,
+        INPUT_SIZE,
+        INPUT_SIZE,
+        "Synthetic detector for tests".to_string(),
+        "green".to_string(),
+        "detect".to_string(),
+        vec!["NMS".to_string()],
+        CLASSES.iter().map(|c| c.to_string()).collect(),
+    )
+}
+
+pub fn tmp_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+// Written once per test binary
+pub fn fixture_bq() -> String {
+    static PATH: OnceLock<String> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let path = tmp_path(&format!("fixture-{}.bq", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        export_bq(&path, &fixture_ai(), &yolo_onnx(INPUT_SIZE, &DETECTIONS)).unwrap();
+        path
+    })
+    .clone()
+}
+
+// Loads the fixture model on the CPU, once per test binary. Loading it again would make
+// it cold under the tests of the health probes
+pub fn setup() {
+    static MODEL: Once = Once::new();
+    MODEL.call_once(|| {
+        let config = SessionConfig {
+            cache_optimized: false,
+            ..SessionConfig::default()
+        };
+        set_model(fixture_bq(), LIST_EPS[0].clone(), &config);
+    });
+}
+
+// A plain gray image, the fixture model ignores the pixels anyway
+pub fn fixture_image(name: &str, width: u32, height: u32) -> String {
+    let path = tmp_path(name);
+    image::RgbImage::from_pixel(width, height, image::Rgb([128, 128, 128]))
+        .save(&path)
+        .unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+// A gray video at 10 fps, with boxes from the fixture model or a fake server
+pub fn fixture_video(name: &str, frames: u32) -> String {
+    video_rs::init().unwrap();
+    let path = tmp_path(name);
+    let size = INPUT_SIZE as usize;
+    let settings = video_rs::encode::Settings::preset_h264_yuv420p(size, size, false);
+    let mut encoder = video_rs::Encoder::new(path.as_path(), settings).unwrap();
+    let duration = video_rs::Time::from_nth_of_a_second(10);
+    let mut position = video_rs::Time::zero();
+    for _ in 0..frames {
+        let frame = ndarray::Array3::from_elem((size, size, 3), 128u8);
+        encoder.encode(&frame, position).unwrap();
+        position = position.aligned_with(duration).add();
+    }
+    encoder.finish().unwrap();
+    path.to_str().unwrap().to_string()
+}
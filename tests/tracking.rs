@@ -0,0 +1,96 @@
+use boquilahub::api::abstractions::{BoundingBoxTrait, BoundingBoxTraitC, XYXYc, XYXY};
+use boquilahub::api::tracking::{count_individuals, Tracker};
+
+fn bbox(x1: f32, y1: f32, size: f32, prob: f32, label: &str) -> XYXYc {
+    XYXYc::new(XYXY::new(x1, y1, x1 + size, y1 + size, prob, 0), label.to_string())
+}
+
+#[test]
+fn ids_are_stable_while_moving() {
+    let mut tracker = Tracker::default();
+    let mut ids = Vec::new();
+
+    for frame in 0..10 {
+        let x = 10.0 + 5.0 * frame as f32;
+        let tracked = tracker.update(
+            vec![bbox(x, 10.0, 40.0, 0.9, "deer"), bbox(200.0, 200.0, 30.0, 0.8, "fox")],
+            frame as f64,
+        );
+        assert_eq!(tracked.len(), 2);
+        ids.push(tracked.iter().map(|b| b.track_id).collect::<Vec<_>>());
+    }
+
+    // Not confirmed until min_hits frames
+    assert!(ids[0].iter().all(|id| id.is_none()));
+    let deer = tracker.update(vec![bbox(60.0, 10.0, 40.0, 0.9, "deer")], 10.0)[0].track_id;
+    assert!(deer.is_some());
+    assert!(ids[2..].iter().all(|frame| frame.contains(&deer)));
+
+    let summaries = tracker.summaries();
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].first_seen, 0.0);
+    assert_eq!(summaries[0].last_seen, 10.0);
+}
+
+#[test]
+fn low_confidence_keeps_tracks_alive_but_never_starts_them() {
+    let mut tracker = Tracker::default();
+    for frame in 0..3 {
+        tracker.update(vec![bbox(10.0, 10.0, 40.0, 0.9, "deer")], frame as f64);
+    }
+
+    let tracked = tracker.update(
+        vec![bbox(11.0, 10.0, 40.0, 0.2, "deer"), bbox(300.0, 300.0, 40.0, 0.2, "deer")],
+        3.0,
+    );
+    // The far one is returned without an id, and no track is started for it
+    assert_eq!(tracked.len(), 2);
+    assert_eq!(tracked[0].track_id, Some(1));
+    assert_eq!(tracked[1].track_id, None);
+    assert_eq!(tracker.tracks.len(), 1);
+}
+
+#[test]
+fn lost_tracks_are_counted_once() {
+    let mut tracker = Tracker::new(0.3, 0.5, 2, 2);
+    for frame in 0..3 {
+        tracker.update(vec![bbox(10.0, 10.0, 40.0, 0.9, "deer")], frame as f64);
+    }
+    for frame in 3..7 {
+        tracker.update(Vec::new(), frame as f64);
+    }
+    for frame in 7..10 {
+        tracker.update(vec![bbox(10.0, 10.0, 40.0, 0.9, "deer")], frame as f64);
+    }
+
+    assert_eq!(tracker.tracks.len(), 1);
+    assert_eq!(tracker.finished.len(), 1);
+    assert_eq!(count_individuals(&tracker.summaries()), vec![("deer".to_string(), 2)]);
+}
+
+#[test]
+fn low_confidence_detections_without_a_track_are_kept() {
+    let mut tracker = Tracker::default();
+    let tracked = tracker.update(vec![bbox(10.0, 10.0, 40.0, 0.46, "deer")], 0.0);
+
+    // Not enough to start a track, but still in the output
+    assert_eq!(tracked.len(), 1);
+    assert_eq!(tracked[0].track_id, None);
+    assert!(tracker.tracks.is_empty());
+}
+
+#[test]
+fn tracks_dont_change_class() {
+    let mut tracker = Tracker::default();
+    for frame in 0..3 {
+        tracker.update(vec![bbox(10.0, 10.0, 40.0, 0.9, "deer")], frame as f64);
+    }
+    let deer = tracker.tracks[0].id;
+
+    // Same place, another species: a new track instead of the deer's
+    let tracked = tracker.update(vec![bbox(10.0, 10.0, 40.0, 0.9, "fox")], 3.0);
+    assert_eq!(tracked[0].track_id, None);
+    assert_eq!(tracker.tracks.len(), 2);
+    assert_eq!(tracker.tracks[0].id, deer);
+    assert_eq!(tracker.tracks[0].last.label, "deer");
+}
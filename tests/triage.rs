@@ -0,0 +1,100 @@
+mod support;
+
+use boquilahub::api::abstractions::{BoundingBoxTrait, BoundingBoxTraitC, PredImg, XYXYc, XYXY};
+use boquilahub::api::export::{sort_by_triage, FileMode};
+use boquilahub::api::triage::{count_by_class, triage, TriageClass};
+use std::path::{Path, PathBuf};
+use support::*;
+
+fn bbox(prob: f32, label: &str) -> XYXYc {
+    XYXYc::new(XYXY::new(0.0, 0.0, 10.0, 10.0, prob, 0), label.to_string())
+}
+
+#[test]
+fn most_confident_box_above_threshold_decides() {
+    let boxes = vec![bbox(0.6, "person"), bbox(0.9, "Deer"), bbox(0.7, "car")];
+    assert_eq!(triage(&boxes, 0.5), TriageClass::Animal);
+    assert_eq!(triage(&boxes[..1], 0.5), TriageClass::Person);
+    assert_eq!(triage(&[bbox(0.7, "Vehículo")], 0.5), TriageClass::Vehicle);
+    assert_eq!(triage(&boxes, 0.95), TriageClass::Empty);
+    assert_eq!(triage(&[], 0.5), TriageClass::Empty);
+}
+
+#[test]
+fn count_by_class_skips_unprocessed_images() {
+    let path = PathBuf::from("unused.jpg");
+    let pred_imgs = vec![
+        PredImg::new(path.clone(), vec![bbox(0.9, "deer")], true),
+        PredImg::new(path.clone(), vec![bbox(0.9, "deer")], true),
+        PredImg::new(path.clone(), vec![bbox(0.3, "person")], true),
+        PredImg::new(path.clone(), vec![bbox(0.9, "person")], false),
+    ];
+    let counts = count_by_class(&pred_imgs, 0.5);
+    assert_eq!(
+        counts,
+        vec![
+            (TriageClass::Animal, 2),
+            (TriageClass::Person, 0),
+            (TriageClass::Vehicle, 0),
+            (TriageClass::Empty, 1),
+        ]
+    );
+}
+
+// An animal and an empty image in a fresh folder, sorted into another one
+async fn sort(mode: FileMode, name: &str) -> (PathBuf, PathBuf, PathBuf) {
+    let input = tmp_path(&format!("triage-{}-in", name));
+    let output = tmp_path(&format!("triage-{}-out", name));
+    let _ = std::fs::remove_dir_all(&input);
+    let _ = std::fs::remove_dir_all(&output);
+    std::fs::create_dir_all(&input).unwrap();
+    let animal = input.join("animal.png");
+    let empty = input.join("empty.png");
+    std::fs::copy(fixture_image("triage.png", 8, 8), &animal).unwrap();
+    std::fs::copy(fixture_image("triage.png", 8, 8), &empty).unwrap();
+
+    let pred_imgs = vec![
+        PredImg::new(animal.clone(), vec![bbox(0.9, "deer")], true),
+        PredImg::new(empty.clone(), vec![], true),
+    ];
+    sort_by_triage(&pred_imgs, output.to_str().unwrap(), 0.5, mode).await;
+    (animal, empty, output)
+}
+
+fn sorted(output: &Path) -> (PathBuf, PathBuf) {
+    (
+        output.join("non_empty/animal/animal.png"),
+        output.join("empty/empty.png"),
+    )
+}
+
+#[tokio::test]
+async fn copy_keeps_the_originals() {
+    let (animal, empty, output) = sort(FileMode::Copy, "copy").await;
+    let (sorted_animal, sorted_empty) = sorted(&output);
+    assert!(sorted_animal.exists() && sorted_empty.exists());
+    assert!(animal.exists() && empty.exists());
+}
+
+#[tokio::test]
+async fn move_removes_the_originals() {
+    let (animal, empty, output) = sort(FileMode::Move, "move").await;
+    let (sorted_animal, sorted_empty) = sorted(&output);
+    assert!(sorted_animal.exists() && sorted_empty.exists());
+    assert!(!animal.exists() && !empty.exists());
+}
+
+#[tokio::test]
+async fn link_keeps_the_originals_with_the_same_content() {
+    let (animal, _, output) = sort(FileMode::Link, "link").await;
+    let (sorted_animal, _) = sorted(&output);
+    assert!(animal.exists());
+    assert_eq!(std::fs::read(&animal).unwrap(), std::fs::read(&sorted_animal).unwrap());
+}
+
+#[test]
+fn file_mode_from_cli_value() {
+    assert_eq!(FileMode::from("Move"), FileMode::Move);
+    assert_eq!(FileMode::from("link"), FileMode::Link);
+    assert_eq!(FileMode::from("anything"), FileMode::Copy);
+}
@@ -0,0 +1,13 @@
+mod support;
+
+use boquilahub::api::video_file::VideofileProcessor;
+use support::*;
+
+// No model is loaded in this binary, so every frame fails
+#[test]
+fn frames_the_model_fails_on_stop_the_video_with_an_error() {
+    let path = fixture_video("no_model.mp4", 4);
+    let mut processor = VideofileProcessor::new(&path);
+    let error = processor.try_predict(1).unwrap_err();
+    assert!(error.contains("No model loaded"), "{}", error);
+}
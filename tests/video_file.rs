@@ -0,0 +1,14 @@
+use boquilahub::api::video_file::{get_output_path, get_tracks_path};
+use std::path::Path;
+
+#[test]
+fn output_goes_next_to_the_input() {
+    let output = get_output_path(Path::new("videos").join("clip.mp4").to_str().unwrap());
+    assert_eq!(Path::new(&output), Path::new("videos").join("predict_clip.mp4"));
+    assert_eq!(get_output_path("clip.mp4"), "predict_clip.mp4");
+}
+
+#[test]
+fn tracks_are_named_after_the_output() {
+    assert_eq!(get_tracks_path("clip.mp4", "tracks"), "predict_clip_tracks.csv");
+}
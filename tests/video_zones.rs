@@ -0,0 +1,73 @@
+mod support;
+
+use axum::{routing::get, routing::post, Json, Router};
+use boquilahub::api::config::{MotionConfig, SmoothingConfig};
+use boquilahub::api::health::Health;
+use boquilahub::api::inference::set_remote;
+use boquilahub::api::video_file::predict_videofile_with_progress;
+use boquilahub::api::zones::ZoneConfig;
+use std::sync::atomic::{AtomicU32, Ordering};
+use support::*;
+
+// The fixture model gives the same boxes for every frame, so an animal that walks
+// from left to right comes from a remote server instead
+static CALLS: AtomicU32 = AtomicU32::new(0);
+
+async fn walking_animal() -> Json<serde_json::Value> {
+    let x = 4.0 + 4.0 * CALLS.fetch_add(1, Ordering::SeqCst) as f32;
+    Json(serde_json::json!([{
+        "xyxy": { "x1": x, "y1": 24.0, "x2": x + 12.0, "y2": 36.0, "prob": 0.9, "class_id": 0 },
+        "label": "animal"
+    }]))
+}
+
+async fn ready() -> Json<Health> {
+    Json(Health {
+        status: "ready".to_string(),
+        version: "test".to_string(),
+        model: None,
+        ep: None,
+        uptime_secs: 0,
+    })
+}
+
+// What the GUI does with a zones file and a video: load the file, then run the video with it
+#[tokio::test]
+async fn zones_of_the_gui_count_crossings_in_videos() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let app = Router::new()
+        .route("/readyz", get(ready))
+        .route("/upload", post(walking_animal));
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    tokio::task::spawn_blocking(move || set_remote(&url))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let zones_path = tmp_path("gui_zones.json");
+    std::fs::write(&zones_path, r#"{ "zones": [{ "name": "gate", "line": [[32, 0], [32, 64]] }] }"#)
+        .unwrap();
+    let zones = ZoneConfig::load(zones_path.to_str().unwrap()).unwrap();
+    let file_path = fixture_video("gui_zones.mp4", 12);
+
+    let summary = tokio::task::spawn_blocking(move || {
+        predict_videofile_with_progress(
+            &file_path,
+            1,
+            &SmoothingConfig::default(),
+            &MotionConfig::default(),
+            Some(zones),
+            |_| {},
+            |_, _| true,
+        )
+    })
+    .await
+    .unwrap()
+    .unwrap();
+
+    assert_eq!(summary.zones.len(), 1);
+    assert_eq!(summary.zones[0].zone, "gate");
+    assert_eq!(summary.zones[0].label, "animal");
+    assert_eq!(summary.zones[0].count, 1);
+}
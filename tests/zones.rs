@@ -0,0 +1,108 @@
+use boquilahub::api::abstractions::{BoundingBoxTrait, BoundingBoxTraitC, XYXYc, XYXY};
+use boquilahub::api::jobs::JobOptions;
+use boquilahub::api::zones::{
+    bin_events, get_live_counts, point_in_polygon, Direction, ZoneConfig, ZoneCounter,
+};
+
+const CONFIG: &str = r#"{
+    "bin_seconds": 10,
+    "zones": [
+        { "name": "pool", "polygon": [[100, 0], [200, 0], [200, 100], [100, 100]] },
+        { "name": "gate", "line": [[50, 0], [50, 100]] }
+    ]
+}"#;
+
+fn tracked(id: u32, cx: f32, cy: f32) -> XYXYc {
+    let mut bbox = XYXYc::new(
+        XYXY::new(cx - 5.0, cy - 5.0, cx + 5.0, cy + 5.0, 0.9, 0),
+        "fish".to_string(),
+    );
+    bbox.track_id = Some(id);
+    bbox
+}
+
+fn counter() -> ZoneCounter {
+    ZoneCounter::new(serde_json::from_str::<ZoneConfig>(CONFIG).unwrap(), false)
+}
+
+#[test]
+fn polygon() {
+    let square = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+    assert!(point_in_polygon([5.0, 5.0], &square));
+    assert!(!point_in_polygon([15.0, 5.0], &square));
+}
+
+#[test]
+fn line_crossings_have_a_direction() {
+    let mut counter = counter();
+    // Looking from (50, 0) to (50, 100), the right side is x < 50
+    for (t, x) in [60.0, 40.0, 30.0, 70.0].iter().enumerate() {
+        counter.update(&[tracked(1, *x, 50.0)], t as f64);
+    }
+
+    let gate: Vec<Direction> = counter
+        .events
+        .iter()
+        .filter(|e| e.zone == "gate")
+        .map(|e| e.direction)
+        .collect();
+    assert_eq!(gate, vec![Direction::Forward, Direction::Backward]);
+}
+
+#[test]
+fn polygons_count_enter_and_exit() {
+    let mut counter = counter();
+    // Starts inside, that's not an entry
+    counter.update(&[tracked(1, 150.0, 50.0), tracked(2, 80.0, 50.0)], 0.0);
+    counter.update(&[tracked(1, 250.0, 50.0), tracked(2, 120.0, 50.0)], 1.0);
+    // Detections without a track id are ignored
+    let mut untracked = tracked(3, 150.0, 50.0);
+    untracked.track_id = None;
+    counter.update(&[tracked(2, 120.0, 50.0), untracked], 12.0);
+
+    let pool: Vec<(u32, Direction)> = counter
+        .events
+        .iter()
+        .filter(|e| e.zone == "pool")
+        .map(|e| (e.track_id, e.direction))
+        .collect();
+    assert_eq!(pool, vec![(1, Direction::Exit), (2, Direction::Enter)]);
+    assert_eq!(counter.counts().len(), 2);
+}
+
+#[test]
+fn events_are_binned() {
+    let mut counter = counter();
+    counter.update(&[tracked(1, 60.0, 50.0)], 0.0);
+    counter.update(&[tracked(1, 40.0, 50.0)], 3.0);
+    counter.update(&[tracked(1, 60.0, 50.0)], 15.0);
+    counter.update(&[tracked(1, 40.0, 50.0)], 18.0);
+
+    let bins = bin_events(&counter.events, counter.config.bin_seconds);
+    assert_eq!(bins.len(), 3);
+    assert_eq!((bins[0].start, bins[0].end), (0.0, 10.0));
+    assert_eq!(bins[0].direction, Direction::Forward);
+    assert_eq!((bins[1].start, bins[1].direction), (10.0, Direction::Forward));
+    assert_eq!((bins[2].start, bins[2].direction), (10.0, Direction::Backward));
+}
+
+#[test]
+fn live_counters_publish_and_start_from_zero() {
+    let config = serde_json::from_str::<ZoneConfig>(CONFIG).unwrap();
+    let mut live = ZoneCounter::new(config.clone(), true);
+    live.update(&[tracked(1, 60.0, 50.0)], 0.0);
+    live.update(&[tracked(1, 40.0, 50.0)], 1.0);
+    assert_eq!(get_live_counts(), live.counts());
+    assert_eq!(get_live_counts()[0].count, 1);
+
+    ZoneCounter::new(config, true);
+    assert!(get_live_counts().is_empty());
+}
+
+#[test]
+fn jobs_take_zones_in_their_options() {
+    let options: JobOptions =
+        serde_json::from_str(&format!(r#"{{ "every_n_frames": 5, "zones": {} }}"#, CONFIG)).unwrap();
+    assert_eq!(options.zones.unwrap().zones.len(), 2);
+    assert!(!options.motion.enabled);
+}